@@ -0,0 +1,37 @@
+//! Checks the syntax of the navigation subsystem from day 10, and prints annotated diagnostics.
+//!
+//! Usage: `cargo run --example day10_check -- [--json] [FILE]`
+//! Reads the lines from standard input if no file is given.
+
+use std::io::{self, Read};
+use std::{env, fs};
+
+use advent_of_code_2021::day10::{
+    check, diagnostics_json, input_generator, render_diagnostics, Brackets,
+};
+
+fn main() -> io::Result<()> {
+    let mut json = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ => path = Some(arg),
+        }
+    }
+    let input = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let diagnostics = check(&input_generator(&input), &Brackets::default());
+    if json {
+        println!("{}", diagnostics_json(&diagnostics));
+    } else {
+        print!("{}", render_diagnostics(&diagnostics));
+    }
+    Ok(())
+}
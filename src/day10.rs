@@ -27,21 +27,295 @@ enum ParseResult {
 }
 
 fn parse(line: &str) -> ParseResult {
-    let mut stack: Vec<char> = vec![];
-    for c in line.chars() {
-        match (stack.last(), c) {
-            (_, '(') => stack.push(')'),
-            (_, '[') => stack.push(']'),
-            (_, '{') => stack.push('}'),
-            (_, '<') => stack.push('>'),
-            (Some(&last_close), close) if last_close == close => {
-                stack.pop().unwrap();
+    let tree = parse_tree(line, &Brackets::default(), Recovery::Skip);
+    match tree.errors.first() {
+        Some(SyntaxError::Corrupted { found, .. }) => ParseResult::Error(*found),
+        Some(SyntaxError::InvalidChar { found, .. }) => ParseResult::Error(*found),
+        _ => ParseResult::Incomplete(tree.completion()),
+    }
+}
+
+/// The pairs of opening and closing characters which delimit chunks.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Brackets {
+    pairs: Vec<(char, char)>,
+}
+
+impl Default for Brackets {
+    fn default() -> Self {
+        Self::new(&[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')])
+    }
+}
+
+impl Brackets {
+    pub fn new(pairs: &[(char, char)]) -> Self {
+        Self {
+            pairs: pairs.to_vec(),
+        }
+    }
+
+    pub fn closer(&self, open: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|&&(o, _)| o == open)
+            .map(|&(_, close)| close)
+    }
+
+    pub fn opener(&self, close: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|&&(_, c)| c == close)
+            .map(|&(open, _)| open)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Chunk {
+    pub open: char,
+    /// Byte position of the opening character
+    pub start: usize,
+    /// Byte position of the closing character, or `None` if the chunk was never closed
+    pub end: Option<usize>,
+    pub children: Vec<Chunk>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SyntaxError {
+    /// A closing character which doesn't close the innermost open chunk
+    Corrupted {
+        pos: usize,
+        expected: Option<char>,
+        found: char,
+    },
+    /// A character which is not part of any bracket pair
+    InvalidChar { pos: usize, found: char },
+    /// A chunk which is still open at the end of the line
+    Unclosed { pos: usize, expected: char },
+}
+
+/// How to continue parsing after a corrupted closing character.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Recovery {
+    /// Ignore the closing character, as if it were deleted.
+    Skip,
+    /// If the closing character closes a chunk further out, close all chunks inside of it first,
+    /// as if their missing closing characters were inserted. Otherwise, ignore it.
+    Insert,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseTree {
+    pub chunks: Vec<Chunk>,
+    pub errors: Vec<SyntaxError>,
+}
+
+impl ParseTree {
+    /// The closing characters needed to close all chunks which are still open.
+    pub fn completion(&self) -> Vec<char> {
+        self.errors
+            .iter()
+            .filter_map(|error| match error {
+                SyntaxError::Unclosed { expected, .. } => Some(*expected),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Parses a line into its chunks, collecting all syntax errors along the way.
+pub fn parse_tree(line: &str, brackets: &Brackets, recovery: Recovery) -> ParseTree {
+    let mut chunks = Vec::<Chunk>::new();
+    let mut errors = Vec::<SyntaxError>::new();
+    let mut stack = Vec::<Chunk>::new();
+    let close_chunk = |stack: &mut Vec<Chunk>, chunks: &mut Vec<Chunk>, end: Option<usize>| {
+        let mut chunk = stack.pop().unwrap();
+        chunk.end = end;
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(chunk),
+            None => chunks.push(chunk),
+        }
+    };
+    for (pos, c) in line.char_indices() {
+        if brackets.closer(c).is_some() {
+            stack.push(Chunk {
+                open: c,
+                start: pos,
+                end: None,
+                children: vec![],
+            });
+            continue;
+        }
+        let open = match brackets.opener(c) {
+            Some(open) => open,
+            None => {
+                errors.push(SyntaxError::InvalidChar { pos, found: c });
+                continue;
+            }
+        };
+        match stack.last() {
+            Some(chunk) if chunk.open == open => {
+                close_chunk(&mut stack, &mut chunks, Some(pos));
+            }
+            last => {
+                errors.push(SyntaxError::Corrupted {
+                    pos,
+                    expected: last.and_then(|chunk| brackets.closer(chunk.open)),
+                    found: c,
+                });
+                if recovery == Recovery::Insert {
+                    if let Some(depth) = stack.iter().rposition(|chunk| chunk.open == open) {
+                        while stack.len() > depth + 1 {
+                            close_chunk(&mut stack, &mut chunks, None);
+                        }
+                        close_chunk(&mut stack, &mut chunks, Some(pos));
+                    }
+                }
+            }
+        }
+    }
+    while let Some(chunk) = stack.last() {
+        errors.push(SyntaxError::Unclosed {
+            pos: chunk.start,
+            expected: brackets.closer(chunk.open).unwrap(),
+        });
+        close_chunk(&mut stack, &mut chunks, None);
+    }
+    ParseTree { chunks, errors }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edit {
+    /// Insert a character before the given byte position
+    Insert(usize, char),
+    /// Delete the character at the given byte position
+    Delete(usize),
+    /// Replace the character at the given byte position
+    Replace(usize, char),
+}
+
+#[derive(Debug, Copy, Clone)]
+enum FixChoice {
+    Empty,
+    Delete,
+    // Pair the first character with the character at the given index
+    Pair(usize),
+    // Pair the first character with a closing character inserted at the given index
+    InsertClose(usize),
+    // Pair the character at the given index with an opening character inserted before the first character
+    InsertOpen(usize),
+}
+
+/// Finds a smallest set of insertions, deletions and replacements which makes the line valid.
+/// Edits are sorted by position, and positions refer to the original line.
+pub fn fix(line: &str, brackets: &Brackets) -> Vec<Edit> {
+    let chars = line.char_indices().collect::<Vec<_>>();
+    let n = chars.len();
+    let is_open = |i: usize| brackets.closer(chars[i].1).is_some();
+    let is_close = |i: usize| brackets.opener(chars[i].1).is_some();
+    let pair_cost = |i: usize, k: usize| {
+        if brackets.closer(chars[i].1) == Some(chars[k].1) {
+            0
+        } else if is_open(i) || is_close(k) {
+            1
+        } else {
+            2
+        }
+    };
+    // cost[i][j] = smallest number of edits to make chars[i..j] valid
+    let mut cost = vec![vec![0usize; n + 1]; n + 1];
+    let mut choice = vec![vec![FixChoice::Empty; n + 1]; n + 1];
+    for len in 1..=n {
+        for i in 0..=n - len {
+            let j = i + len;
+            let best = std::iter::once((1 + cost[i + 1][j], FixChoice::Delete))
+                .chain((i + 1..j).map(|k| {
+                    let candidate = pair_cost(i, k) + cost[i + 1][k] + cost[k + 1][j];
+                    (candidate, FixChoice::Pair(k))
+                }))
+                .chain((i + 1..=j).filter(|_| is_open(i)).map(|k| {
+                    let candidate = 1 + cost[i + 1][k] + cost[k][j];
+                    (candidate, FixChoice::InsertClose(k))
+                }))
+                .chain((i..j).filter(|&k| is_close(k)).map(|k| {
+                    let candidate = 1 + cost[i][k] + cost[k + 1][j];
+                    (candidate, FixChoice::InsertOpen(k))
+                }))
+                .min_by_key(|&(candidate, _)| candidate)
+                .unwrap();
+            cost[i][j] = best.0;
+            choice[i][j] = best.1;
+        }
+    }
+    // Reconstruct the edits
+    let byte_pos = |i: usize| chars.get(i).map_or(line.len(), |&(pos, _)| pos);
+    let default_pair = brackets.pairs[0];
+    let mut edits = Vec::new();
+    let mut todo = vec![(0, n)];
+    while let Some((i, j)) = todo.pop() {
+        match choice[i][j] {
+            FixChoice::Empty => {}
+            FixChoice::Delete => {
+                edits.push(Edit::Delete(byte_pos(i)));
+                todo.push((i + 1, j));
+            }
+            FixChoice::Pair(k) => {
+                let (open, close) = (chars[i].1, chars[k].1);
+                match (brackets.closer(open), brackets.opener(close)) {
+                    (Some(expected), _) if expected == close => {}
+                    (Some(expected), _) => edits.push(Edit::Replace(byte_pos(k), expected)),
+                    (None, Some(expected)) => edits.push(Edit::Replace(byte_pos(i), expected)),
+                    (None, None) => {
+                        edits.push(Edit::Replace(byte_pos(i), default_pair.0));
+                        edits.push(Edit::Replace(byte_pos(k), default_pair.1));
+                    }
+                }
+                todo.push((i + 1, k));
+                todo.push((k + 1, j));
+            }
+            FixChoice::InsertClose(k) => {
+                let close = brackets.closer(chars[i].1).unwrap();
+                edits.push(Edit::Insert(byte_pos(k), close));
+                todo.push((i + 1, k));
+                todo.push((k, j));
+            }
+            FixChoice::InsertOpen(k) => {
+                let open = brackets.opener(chars[k].1).unwrap();
+                edits.push(Edit::Insert(byte_pos(i), open));
+                todo.push((i, k));
+                todo.push((k + 1, j));
+            }
+        }
+    }
+    edits.sort_by_key(|edit| match *edit {
+        Edit::Insert(pos, _) => (pos, 0),
+        Edit::Delete(pos) | Edit::Replace(pos, _) => (pos, 1),
+    });
+    edits
+}
+
+/// Applies edits (as returned by `fix`) to the original line.
+pub fn apply_edits(line: &str, edits: &[Edit]) -> String {
+    let mut result = String::new();
+    let mut edits = edits.iter().peekable();
+    for (pos, c) in line.char_indices().chain([(line.len(), '\0')]) {
+        let mut keep = pos < line.len();
+        while let Some(edit) = edits.next_if(|edit| match **edit {
+            Edit::Insert(p, _) | Edit::Delete(p) | Edit::Replace(p, _) => p == pos,
+        }) {
+            match *edit {
+                Edit::Insert(_, inserted) => result.push(inserted),
+                Edit::Delete(_) => keep = false,
+                Edit::Replace(_, replacement) => {
+                    result.push(replacement);
+                    keep = false;
+                }
             }
-            (_, c) => return ParseResult::Error(c),
+        }
+        if keep {
+            result.push(c);
         }
     }
-    stack.reverse();
-    ParseResult::Incomplete(stack)
+    result
 }
 
 #[aoc(day10, part2)]
@@ -50,7 +324,9 @@ pub fn part2(input: &[String]) -> i64 {
         .iter()
         .map(|s| parse(s))
         .filter_map(|result| match result {
-            ParseResult::Incomplete(completion) => Some(autocomplete_score(&completion)),
+            ParseResult::Incomplete(completion) => {
+                Some(autocomplete_score(&completion, &Brackets::default()))
+            }
             ParseResult::Error(_) => None,
         })
         .collect::<Vec<_>>();
@@ -59,21 +335,184 @@ pub fn part2(input: &[String]) -> i64 {
     scores[scores.len() / 2]
 }
 
-fn autocomplete_score(completion: &[char]) -> i64 {
+/// Scores each closing character by the position of its pair (starting from 1),
+/// so with the default brackets this is `)` = 1, `]` = 2, `}` = 3 and `>` = 4.
+fn autocomplete_score(completion: &[char], brackets: &Brackets) -> i64 {
+    let base = brackets.pairs.len() as i64 + 1;
     let mut score = 0;
     for &c in completion {
-        score *= 5;
-        score += match c {
-            ')' => 1,
-            ']' => 2,
-            '}' => 3,
-            '>' => 4,
-            c => panic!("unexpected char {}", c),
+        score *= base;
+        score += match brackets.pairs.iter().position(|&(_, close)| close == c) {
+            Some(index) => index as i64 + 1,
+            None => panic!("unexpected char {}", c),
         }
     }
     score
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Line number, starting from 1
+    pub line: usize,
+    pub text: String,
+    /// All corrupted and invalid characters on the line
+    pub errors: Vec<SyntaxError>,
+    /// The closing characters to append after skipping over the errors
+    pub completion: String,
+    /// The autocomplete score, only for lines which are not corrupted
+    pub score: Option<i64>,
+}
+
+impl Diagnostic {
+    pub fn status(&self) -> &'static str {
+        if !self.errors.is_empty() {
+            "corrupted"
+        } else if !self.completion.is_empty() {
+            "incomplete"
+        } else {
+            "valid"
+        }
+    }
+}
+
+pub fn check(lines: &[String], brackets: &Brackets) -> Vec<Diagnostic> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, text)| {
+            let tree = parse_tree(text, brackets, Recovery::Skip);
+            let completion = tree.completion();
+            let errors = tree
+                .errors
+                .into_iter()
+                .filter(|error| !matches!(error, SyntaxError::Unclosed { .. }))
+                .collect::<Vec<_>>();
+            let score = if errors.is_empty() {
+                Some(autocomplete_score(&completion, brackets))
+            } else {
+                None
+            };
+            Diagnostic {
+                line: index + 1,
+                text: text.clone(),
+                errors,
+                completion: completion.into_iter().collect(),
+                score,
+            }
+        })
+        .collect()
+}
+
+/// Converts a byte position into a column (counted in characters, starting from 1).
+fn column(text: &str, pos: usize) -> usize {
+    text[..pos].chars().count() + 1
+}
+
+/// Prints every line with a caret under each corrupted character,
+/// followed by the completion and its autocomplete score.
+/// Corrupted lines are completed after skipping over their errors, but they have no score.
+pub fn render_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    let mut result = String::new();
+    for diagnostic in diagnostics {
+        let text = &diagnostic.text;
+        result += &format!("{:>4} | {}\n", diagnostic.line, text);
+        for error in &diagnostic.errors {
+            let (pos, message) = match *error {
+                SyntaxError::Corrupted {
+                    pos,
+                    expected: Some(expected),
+                    found,
+                } => (pos, format!("expected '{}', found '{}'", expected, found)),
+                SyntaxError::Corrupted {
+                    pos,
+                    expected: None,
+                    found,
+                } => (pos, format!("unexpected '{}'", found)),
+                SyntaxError::InvalidChar { pos, found } => {
+                    (pos, format!("invalid character '{}'", found))
+                }
+                SyntaxError::Unclosed { .. } => unreachable!(),
+            };
+            let padding = " ".repeat(column(text, pos) - 1);
+            result += &format!("     | {}^ {}\n", padding, message);
+        }
+        let score = match diagnostic.score {
+            Some(score) => format!("score {}", score),
+            None => "no score, since the line is corrupted".to_string(),
+        };
+        result += &format!(
+            "     = complete with '{}' ({})\n",
+            diagnostic.completion, score
+        );
+    }
+    result
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            c if c.is_control() => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn json_char(c: Option<char>) -> String {
+    match c {
+        Some(c) => json_string(&c.to_string()),
+        None => "null".to_string(),
+    }
+}
+
+/// Serializes the diagnostics as a JSON array, with one object per line.
+/// Positions are given both as a byte offset and as a column (in characters, starting from 1).
+pub fn diagnostics_json(diagnostics: &[Diagnostic]) -> String {
+    let objects = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let errors = diagnostic
+                .errors
+                .iter()
+                .map(|error| {
+                    let (kind, pos, expected, found) = match *error {
+                        SyntaxError::Corrupted {
+                            pos,
+                            expected,
+                            found,
+                        } => ("corrupted", pos, expected, found),
+                        SyntaxError::InvalidChar { pos, found } => ("invalid", pos, None, found),
+                        SyntaxError::Unclosed { .. } => unreachable!(),
+                    };
+                    format!(
+                        r#"{{"kind":"{}","offset":{},"column":{},"expected":{},"found":{}}}"#,
+                        kind,
+                        pos,
+                        column(&diagnostic.text, pos),
+                        json_char(expected),
+                        json_char(Some(found))
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(
+                r#"{{"line":{},"status":"{}","errors":[{}],"completion":{},"score":{}}}"#,
+                diagnostic.line,
+                diagnostic.status(),
+                errors.join(","),
+                json_string(&diagnostic.completion),
+                diagnostic
+                    .score
+                    .map_or("null".to_string(), |score| score.to_string())
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", objects.join(","))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +544,126 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 288957);
     }
+
+    #[test]
+    fn test_parse_tree() {
+        let brackets = Brackets::default();
+        let tree = parse_tree("([]<)", &brackets, Recovery::Skip);
+        assert_eq!(
+            tree.errors,
+            vec![
+                SyntaxError::Corrupted {
+                    pos: 4,
+                    expected: Some('>'),
+                    found: ')'
+                },
+                SyntaxError::Unclosed {
+                    pos: 3,
+                    expected: '>'
+                },
+                SyntaxError::Unclosed {
+                    pos: 0,
+                    expected: ')'
+                },
+            ]
+        );
+        assert_eq!(tree.chunks.len(), 1);
+        assert_eq!(tree.chunks[0].children.len(), 2);
+        assert_eq!(tree.chunks[0].children[0].end, Some(2));
+
+        let tree = parse_tree("([]<)", &brackets, Recovery::Insert);
+        assert_eq!(tree.errors.len(), 1);
+        assert_eq!(
+            tree.chunks,
+            vec![Chunk {
+                open: '(',
+                start: 0,
+                end: Some(4),
+                children: vec![
+                    Chunk {
+                        open: '[',
+                        start: 1,
+                        end: Some(2),
+                        children: vec![]
+                    },
+                    Chunk {
+                        open: '<',
+                        start: 3,
+                        end: None,
+                        children: vec![]
+                    }
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_custom_brackets() {
+        let brackets = Brackets::new(&[('«', '»'), ('(', ')')]);
+        let tree = parse_tree("«(»)x", &brackets, Recovery::Skip);
+        assert_eq!(
+            tree.errors,
+            vec![
+                SyntaxError::Corrupted {
+                    pos: 3,
+                    expected: Some(')'),
+                    found: '»'
+                },
+                SyntaxError::InvalidChar { pos: 6, found: 'x' },
+                SyntaxError::Unclosed {
+                    pos: 0,
+                    expected: '»'
+                }
+            ]
+        );
+        assert_eq!(tree.completion(), vec!['»']);
+    }
+
+    #[test]
+    fn test_fix() {
+        let brackets = Brackets::default();
+        assert_eq!(fix("(]", &brackets), vec![Edit::Replace(1, ')')]);
+        assert_eq!(fix("(()", &brackets).len(), 1);
+        assert_eq!(fix("())", &brackets).len(), 1);
+        assert_eq!(fix("<(>)", &brackets).len(), 2);
+        assert_eq!(fix("[<>({}){}[([])<>]]", &brackets), vec![]);
+        for line in input_generator(&TEST_INPUT) {
+            let edits = fix(&line, &brackets);
+            let fixed = apply_edits(&line, &edits);
+            let tree = parse_tree(&fixed, &brackets, Recovery::Skip);
+            assert_eq!(tree.errors, vec![], "{} fixed as {}", line, fixed);
+            if let ParseResult::Incomplete(completion) = parse(&line) {
+                // Appending the completion is valid, but not necessarily minimal
+                assert!(edits.len() <= completion.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let lines = ["{([(<{}[<>[]}>{[]{[(<()>", "[({(<(())[]>[[{[]{<()<>>", "()"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        let diagnostics = check(&lines, &Brackets::default());
+        assert_eq!(diagnostics[0].status(), "corrupted");
+        assert_eq!(diagnostics[1].status(), "incomplete");
+        assert_eq!(diagnostics[1].score, Some(288957));
+        assert_eq!(diagnostics[2].status(), "valid");
+        let rendered = render_diagnostics(&diagnostics);
+        assert!(rendered.starts_with(
+            "   1 | {([(<{}[<>[]}>{[]{[(<()>\n     |             ^ expected ']', found '}'\n"
+        ));
+        assert!(rendered.contains("     = complete with '}}]])})]' (score 288957)\n"));
+        assert!(rendered.contains(
+            "     = complete with ')]}}]>)])}' (no score, since the line is corrupted)\n"
+        ));
+        assert!(rendered.ends_with("   3 | ()\n     = complete with '' (score 0)\n"));
+        let json = diagnostics_json(&diagnostics[2..]);
+        assert_eq!(
+            json,
+            r#"[{"line":3,"status":"valid","errors":[],"completion":"","score":0}]"#
+        );
+        assert_eq!(json_string("\"a\\b\n"), r#""\"a\\b\u000a""#);
+    }
 }
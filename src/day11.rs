@@ -1,6 +1,9 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
 use crate::util::Vector2D;
 
-type Octopuses = [[u8; 10]; 10];
+type Octopuses = Vec<Vec<u8>>;
 
 #[aoc_generator(day11)]
 pub fn input_generator(input: &str) -> Octopuses {
@@ -9,66 +12,80 @@ pub fn input_generator(input: &str) -> Octopuses {
         .map(|line| {
             line.chars()
                 .map(|c| c.to_digit(10).unwrap() as u8)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap()
+                .collect()
         })
-        .collect::<Vec<_>>()
-        .try_into()
-        .unwrap()
+        .collect()
 }
 
 #[aoc(day11, part1)]
-pub fn part1(octopuses: &Octopuses) -> i32 {
-    let mut octopuses = *octopuses;
+pub fn part1(octopuses: &Octopuses) -> usize {
+    let mut octopuses = octopuses.clone();
     let mut flashes = 0;
     for _ in 1..=100 {
-        flashes += step(&mut octopuses);
+        flashes += step(&mut octopuses).flashes();
     }
     flashes
 }
 
-fn step(octopuses: &mut Octopuses) -> i32 {
-    let mut flashes = 0;
+/// The octopuses which flashed during a single step, grouped by cascade wave.
+/// The first wave contains the octopuses which flashed from the initial energy increase,
+/// every next wave contains the octopuses which were pushed over the edge by the previous wave.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StepLog {
+    pub waves: Vec<Vec<Vector2D>>,
+}
+
+impl StepLog {
+    pub fn flashes(&self) -> usize {
+        self.waves.iter().map(|wave| wave.len()).sum()
+    }
+}
+
+fn step(octopuses: &mut Octopuses) -> StepLog {
+    let mut log = StepLog::default();
     // First, the energy level of each octopus increases by 1.
-    for row in octopuses.iter_mut() {
-        for octopus in row {
+    // Then, any octopus with an energy level greater than 9 flashes.
+    let mut wave = Vec::new();
+    for (y, row) in octopuses.iter_mut().enumerate() {
+        for (x, octopus) in row.iter_mut().enumerate() {
             *octopus += 1;
+            if *octopus > 9 {
+                wave.push(Vector2D::new(x as i32, y as i32));
+            }
         }
     }
-    // Then, any octopus with an energy level greater than 9 flashes.
-    // This process continues as long as new octopuses keep having
-    // their energy level increased beyond 9.
-    let height = octopuses.len();
-    let width = octopuses[0].len();
-    loop {
-        let prev_flashes = flashes;
-        for y in 0..height {
-            for x in 0..width {
-                let octopus = &mut octopuses[y][x];
-                if *octopus > 9 {
-                    flashes += 1;
-                    // Any octopus that flashed during this step
-                    // has its energy level set to 0
-                    *octopus = 0;
-                    // This increases the energy level of all adjacent octopuses by 1.
-                    let pos = Vector2D::new(x as i32, y as i32);
-                    for neighbour_pos in get_neighbours(octopuses, pos) {
-                        let neighbour =
-                            &mut octopuses[neighbour_pos.y() as usize][neighbour_pos.x() as usize];
-                        // 0 means it has already flashed during this step, so don't flash again.
-                        if *neighbour != 0 {
-                            *neighbour += 1;
-                        }
-                    }
+    // This increases the energy level of all adjacent octopuses by 1.
+    // An octopus joins the next wave at the moment its energy level goes beyond 9,
+    // so it is processed at most once during this step.
+    while !wave.is_empty() {
+        let mut next_wave = Vec::new();
+        for &pos in &wave {
+            for neighbour_pos in get_neighbours(octopuses, pos) {
+                let neighbour =
+                    &mut octopuses[neighbour_pos.y() as usize][neighbour_pos.x() as usize];
+                *neighbour += 1;
+                if *neighbour == 10 {
+                    next_wave.push(neighbour_pos);
                 }
             }
         }
-        if flashes == prev_flashes {
-            break;
+        log.waves.push(wave);
+        wave = next_wave;
+    }
+    // Any octopus that flashed during this step has its energy level set to 0
+    for wave in &log.waves {
+        for pos in wave {
+            octopuses[pos.y() as usize][pos.x() as usize] = 0;
         }
     }
-    flashes
+    log
+}
+
+/// Runs the given number of steps, and records which octopuses flashed in each step.
+#[allow(dead_code)]
+pub fn flash_log(octopuses: &Octopuses, steps: usize) -> Vec<StepLog> {
+    let mut octopuses = octopuses.clone();
+    (0..steps).map(|_| step(&mut octopuses)).collect()
 }
 
 fn get_neighbours(octopuses: &Octopuses, pos: Vector2D) -> Vec<Vector2D> {
@@ -77,7 +94,7 @@ fn get_neighbours(octopuses: &Octopuses, pos: Vector2D) -> Vec<Vector2D> {
             pos.y() >= 0
                 && pos.y() < octopuses.len() as i32
                 && pos.x() >= 0
-                && pos.x() < octopuses[0].len() as i32
+                && pos.x() < octopuses[pos.y() as usize].len() as i32
         })
         .collect()
 }
@@ -91,15 +108,108 @@ fn print_grid(octopuses: &Octopuses) {
 }
 
 #[aoc(day11, part2)]
-pub fn part2(octopuses: &Octopuses) -> i32 {
-    let mut octopuses = *octopuses;
-    let mut i = 0;
+pub fn part2(octopuses: &Octopuses) -> usize {
+    match analyse(octopuses, usize::MAX).sync {
+        Synchronisation::At(step) => step,
+        sync => panic!("octopuses never synchronise: {:?}", sync),
+    }
+}
+
+fn state_hash(octopuses: &Octopuses) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    octopuses.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn is_synchronised(octopuses: &Octopuses) -> bool {
+    octopuses.iter().flatten().all(|&octopus| octopus == 0)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cycle {
+    /// The first step after which the grid is in a state which repeats forever
+    pub start: usize,
+    /// The number of steps after which the states repeat
+    pub period: usize,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Synchronisation {
+    /// All octopuses flash simultaneously for the first time during this step
+    At(usize),
+    /// The states repeat without ever being synchronised, so they never will be
+    Never,
+    /// No synchronisation within the bound, but also no cycle to prove it never happens
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CycleAnalysis {
+    pub cycle: Option<Cycle>,
+    pub sync: Synchronisation,
+}
+
+/// Finds the cycle in the sequence of grid states using Brent's algorithm,
+/// simulating at most `bound` steps to find it.
+///
+/// All octopuses flash simultaneously exactly when the grid becomes all zeros.
+/// Once the cycle is found, every state in it has been visited at least once,
+/// so if none of them were synchronised then the octopuses never will be.
+pub fn analyse(octopuses: &Octopuses, bound: usize) -> CycleAnalysis {
+    let mut first_sync = None;
+    // Find the period: the hare moves one step at a time,
+    // and the tortoise teleports to the hare after every power of two steps.
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = octopuses.clone();
+    let mut tortoise_hash = state_hash(&tortoise);
+    let mut hare = octopuses.clone();
+    let mut hare_steps = 0;
     loop {
-        i += 1;
-        let flashes = step(&mut octopuses);
-        if flashes == 10 * 10 {
-            return i;
+        if hare_steps == bound {
+            let sync = match first_sync {
+                Some(step) => Synchronisation::At(step),
+                None => Synchronisation::Unknown,
+            };
+            return CycleAnalysis { cycle: None, sync };
+        }
+        step(&mut hare);
+        hare_steps += 1;
+        if first_sync.is_none() && is_synchronised(&hare) {
+            first_sync = Some(hare_steps);
         }
+        let hare_hash = state_hash(&hare);
+        if hare_hash == tortoise_hash && hare == tortoise {
+            break;
+        }
+        if power == period {
+            tortoise = hare.clone();
+            tortoise_hash = hare_hash;
+            power *= 2;
+            period = 0;
+        }
+        period += 1;
+    }
+    // Find the start: move the hare `period` steps ahead of the tortoise,
+    // then move both until they meet.
+    let mut tortoise = octopuses.clone();
+    let mut hare = octopuses.clone();
+    for _ in 0..period {
+        step(&mut hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        start += 1;
+    }
+    let sync = match first_sync {
+        Some(step) => Synchronisation::At(step),
+        None => Synchronisation::Never,
+    };
+    CycleAnalysis {
+        cycle: Some(Cycle { start, period }),
+        sync,
     }
 }
 
@@ -134,4 +244,70 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 195);
     }
+
+    #[test]
+    fn test_waves() {
+        let input = input_generator(
+            r"
+11111
+19991
+19191
+19991
+11111
+"
+            .trim(),
+        );
+        let log = flash_log(&input, 2);
+        // The ring of nines flashes first, which then makes the center flash
+        assert_eq!(log[0].waves.len(), 2);
+        assert_eq!(log[0].waves[0].len(), 8);
+        assert_eq!(log[0].waves[1], vec![Vector2D::new(2, 2)]);
+        assert_eq!(log[1], StepLog::default());
+        let mut octopuses = input;
+        step(&mut octopuses);
+        assert_eq!(
+            octopuses,
+            input_generator("34543\n40004\n50005\n40004\n34543")
+        );
+    }
+
+    #[test]
+    fn test_non_square() {
+        let input = input_generator("999\n989\n999\n111\n111\n112");
+        let log = flash_log(&input, 1);
+        assert_eq!(log[0].flashes(), 9);
+        let mut octopuses = input;
+        step(&mut octopuses);
+        assert_eq!(octopuses, input_generator("000\n000\n000\n454\n222\n223"));
+    }
+
+    #[test]
+    fn test_analyse() {
+        let input = input_generator(&TEST_INPUT);
+        let analysis = analyse(&input, usize::MAX);
+        assert_eq!(analysis.sync, Synchronisation::At(195));
+        let cycle = analysis.cycle.unwrap();
+        assert!(cycle.start <= 195);
+        // Once synchronised, the octopuses flash together every 10 steps
+        assert_eq!(cycle.period, 10);
+        assert_eq!(
+            analyse(&input, 100),
+            CycleAnalysis {
+                cycle: None,
+                sync: Synchronisation::Unknown
+            }
+        );
+        // These two octopuses take turns flashing, and repeat every 9 steps without ever flashing together
+        let input = input_generator("2\n0");
+        assert_eq!(
+            analyse(&input, 100),
+            CycleAnalysis {
+                cycle: Some(Cycle {
+                    start: 0,
+                    period: 9
+                }),
+                sync: Synchronisation::Never
+            }
+        );
+    }
 }
@@ -1,6 +1,7 @@
-use std::collections::VecDeque;
-
-use multimap::MultiMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 #[aoc_generator(day12)]
 pub fn input_generator(input: &str) -> Vec<(String, String)> {
@@ -13,51 +14,389 @@ pub fn input_generator(input: &str) -> Vec<(String, String)> {
         .collect()
 }
 
-type Path = Vec<String>;
+pub type CaveId = usize;
+
+/// A cave system, where every cave name is interned into a `CaveId`.
+#[derive(Debug, Clone)]
+pub struct CaveGraph {
+    names: Vec<String>,
+    ids: HashMap<String, CaveId>,
+    small: Vec<bool>,
+    /// For each cave, its neighbours along with the weight of the edge to them
+    neighbours: Vec<Vec<(CaveId, u64)>>,
+}
+
+/// Which caves can be visited, and how often.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VisitPolicy {
+    pub start: String,
+    pub end: String,
+    /// Overrides how many times a cave can be visited.
+    /// By default, small caves can be visited once and large caves can be visited any number of times.
+    pub limits: HashMap<String, u32>,
+    /// How many extra visits can be spent on caves which have already reached their limit.
+    /// The start and end caves cannot be revisited, and neither can caves with a limit of zero.
+    pub revisits: u32,
+}
+
+impl Default for VisitPolicy {
+    fn default() -> Self {
+        Self {
+            start: "start".to_string(),
+            end: "end".to_string(),
+            limits: HashMap::new(),
+            revisits: 0,
+        }
+    }
+}
 
+/// A visit policy resolved against a specific graph.
 #[derive(Debug, Clone)]
-pub struct Node {
-    path: Path,
-    did_revisit_small_cave: bool,
+struct Rules {
+    start: CaveId,
+    end: CaveId,
+    /// For each cave with a limit, its limit and its index in `State::visits`
+    limits: Vec<Option<(u32, usize)>>,
+    revisits: u32,
+}
+
+/// Everything which determines how a path can continue.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct State {
+    cave: CaveId,
+    /// How many times each cave with a limit has been visited so far
+    visits: Vec<u32>,
+    /// How many revisits are left
+    revisits: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WeightedPath<'a> {
+    pub weight: u64,
+    pub caves: Vec<&'a str>,
+}
+
+type OptimumMemo = HashMap<State, Option<(u64, Option<State>)>>;
+
+impl CaveGraph {
+    pub fn new(edges: &[(String, String)]) -> Self {
+        let edges = edges
+            .iter()
+            .map(|(left, right)| (left.clone(), right.clone(), 1))
+            .collect::<Vec<_>>();
+        Self::with_weights(&edges)
+    }
+
+    pub fn with_weights(edges: &[(String, String, u64)]) -> Self {
+        let mut graph = CaveGraph {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            small: Vec::new(),
+            neighbours: Vec::new(),
+        };
+        for (left, right, weight) in edges {
+            let left = graph.intern(left);
+            let right = graph.intern(right);
+            graph.neighbours[left].push((right, *weight));
+            graph.neighbours[right].push((left, *weight));
+        }
+        graph
+    }
+
+    fn intern(&mut self, name: &str) -> CaveId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.small.push(is_small_cave(name));
+        self.neighbours.push(Vec::new());
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<CaveId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, cave: CaveId) -> &str {
+        &self.names[cave]
+    }
+
+    #[allow(dead_code)]
+    pub fn is_small(&self, cave: CaveId) -> bool {
+        self.small[cave]
+    }
+
+    fn rules(&self, policy: &VisitPolicy) -> Rules {
+        let start = self.id(&policy.start).expect("missing start cave");
+        let end = self.id(&policy.end).expect("missing end cave");
+        let mut slots = 0;
+        let limits = (0..self.names.len())
+            .map(|cave| {
+                let limit = match policy.limits.get(self.name(cave)) {
+                    _ if cave == start || cave == end => Some(1),
+                    Some(&limit) => Some(limit),
+                    None if self.small[cave] => Some(1),
+                    None => None,
+                };
+                limit.map(|limit| {
+                    slots += 1;
+                    (limit, slots - 1)
+                })
+            })
+            .collect::<Vec<_>>();
+        // Going back and forth between two unlimited caves would result in infinitely many paths
+        for (cave, neighbours) in self.neighbours.iter().enumerate() {
+            for &(next, _) in neighbours {
+                assert!(
+                    limits[cave].is_some() || limits[next].is_some(),
+                    "caves {} and {} can be visited infinitely often",
+                    self.name(cave),
+                    self.name(next)
+                );
+            }
+        }
+        Rules {
+            start,
+            end,
+            limits,
+            revisits: policy.revisits,
+        }
+    }
+
+    fn initial_state(&self, rules: &Rules) -> State {
+        let slots = rules.limits.iter().flatten().count();
+        let mut state = State {
+            cave: rules.start,
+            visits: vec![0; slots],
+            revisits: rules.revisits,
+        };
+        let (_, slot) = rules.limits[rules.start].unwrap();
+        state.visits[slot] = 1;
+        state
+    }
+
+    /// Moves to the next cave, if the rules allow it.
+    fn visit(&self, rules: &Rules, state: &State, next: CaveId) -> Option<State> {
+        let mut state = state.clone();
+        state.cave = next;
+        if let Some((limit, slot)) = rules.limits[next] {
+            if state.visits[slot] < limit {
+                state.visits[slot] += 1;
+            } else if state.revisits > 0 && limit > 0 && next != rules.start && next != rules.end {
+                state.revisits -= 1;
+            } else {
+                return None;
+            }
+        }
+        Some(state)
+    }
+
+    /// Counts the paths from start to end which are allowed by the given policy.
+    ///
+    /// Since the number of paths can be huge, they are not enumerated one by one.
+    /// Instead, the number of ways to reach the end only depends on the current cave,
+    /// the visits to limited caves so far and the remaining revisits,
+    /// so the counts for each such state are memoised.
+    pub fn count_paths(&self, policy: &VisitPolicy) -> u64 {
+        let rules = self.rules(policy);
+        let mut memo = HashMap::new();
+        self.count_paths_from(&rules, self.initial_state(&rules), &mut memo)
+    }
+
+    fn count_paths_from(&self, rules: &Rules, state: State, memo: &mut HashMap<State, u64>) -> u64 {
+        if state.cave == rules.end {
+            return 1;
+        }
+        if let Some(&count) = memo.get(&state) {
+            return count;
+        }
+        let mut count = 0;
+        for &(next, _) in &self.neighbours[state.cave] {
+            if let Some(next_state) = self.visit(rules, &state, next) {
+                count += self.count_paths_from(rules, next_state, memo);
+            }
+        }
+        memo.insert(state, count);
+        count
+    }
+
+    /// Finds a path with the smallest total weight.
+    #[allow(dead_code)]
+    pub fn shortest_path(&self, policy: &VisitPolicy) -> Option<WeightedPath<'_>> {
+        self.optimal_path(policy, |weight, best| weight < best)
+    }
+
+    /// Finds a path with the largest total weight.
+    #[allow(dead_code)]
+    pub fn longest_path(&self, policy: &VisitPolicy) -> Option<WeightedPath<'_>> {
+        self.optimal_path(policy, |weight, best| weight > best)
+    }
+
+    fn optimal_path(
+        &self,
+        policy: &VisitPolicy,
+        is_better: fn(u64, u64) -> bool,
+    ) -> Option<WeightedPath<'_>> {
+        let rules = self.rules(policy);
+        let mut memo = OptimumMemo::new();
+        let initial = self.initial_state(&rules);
+        let weight = self.optimum_from(&rules, initial.clone(), is_better, &mut memo)?;
+        // Follow the best choices from the start
+        let mut caves = vec![self.name(initial.cave)];
+        let mut state = initial;
+        while let Some((_, Some(next_state))) = memo.get(&state).cloned().flatten() {
+            caves.push(self.name(next_state.cave));
+            state = next_state;
+        }
+        Some(WeightedPath { weight, caves })
+    }
+
+    fn optimum_from(
+        &self,
+        rules: &Rules,
+        state: State,
+        is_better: fn(u64, u64) -> bool,
+        memo: &mut OptimumMemo,
+    ) -> Option<u64> {
+        if state.cave == rules.end {
+            memo.insert(state, Some((0, None)));
+            return Some(0);
+        }
+        if let Some(best) = memo.get(&state) {
+            return best.as_ref().map(|&(weight, _)| weight);
+        }
+        let mut best: Option<(u64, Option<State>)> = None;
+        for &(next, edge_weight) in &self.neighbours[state.cave] {
+            if let Some(next_state) = self.visit(rules, &state, next) {
+                if let Some(weight) = self.optimum_from(rules, next_state.clone(), is_better, memo)
+                {
+                    let weight = edge_weight + weight;
+                    if best
+                        .as_ref()
+                        .map_or(true, |&(best, _)| is_better(weight, best))
+                    {
+                        best = Some((weight, Some(next_state)));
+                    }
+                }
+            }
+        }
+        let result = best.as_ref().map(|&(weight, _)| weight);
+        memo.insert(state, best);
+        result
+    }
+
+    /// Lazily enumerates the same paths as `count_paths`, using a depth-first search.
+    #[allow(dead_code)]
+    pub fn paths(&self, policy: &VisitPolicy) -> Paths<'_> {
+        let rules = self.rules(policy);
+        let initial = self.initial_state(&rules);
+        Paths {
+            graph: self,
+            rules,
+            stack: vec![(initial, 0)],
+        }
+    }
+
+    /// Writes the cave system as an undirected Graphviz graph.
+    /// Small caves are drawn as ellipses, large caves as filled boxes.
+    /// If a path is given, its caves and edges are highlighted.
+    #[allow(dead_code)]
+    pub fn write_dot(&self, out: &mut impl Write, highlight: Option<&[&str]>) -> io::Result<()> {
+        let path = highlight.unwrap_or(&[]);
+        let highlighted_edges = path
+            .windows(2)
+            .map(|pair| {
+                let (left, right) = (self.id(pair[0]), self.id(pair[1]));
+                (left.min(right), left.max(right))
+            })
+            .collect::<HashSet<_>>();
+        writeln!(out, "graph caves {{")?;
+        for (cave, name) in self.names.iter().enumerate() {
+            let mut style = if self.small[cave] {
+                "shape=ellipse".to_string()
+            } else {
+                "shape=box, style=filled, fillcolor=lightgrey".to_string()
+            };
+            if path.contains(&name.as_str()) {
+                style += ", color=red, penwidth=2";
+            }
+            writeln!(out, "    \"{}\" [{}];", name, style)?;
+        }
+        for (cave, neighbours) in self.neighbours.iter().enumerate() {
+            for &(next, weight) in neighbours {
+                // Every edge is stored in both directions, only write it once
+                if next < cave {
+                    continue;
+                }
+                let mut attributes = Vec::new();
+                if weight != 1 {
+                    attributes.push(format!("label={}", weight));
+                }
+                if highlighted_edges.contains(&(Some(cave), Some(next))) {
+                    attributes.push("color=red, penwidth=2".to_string());
+                }
+                write!(
+                    out,
+                    "    \"{}\" -- \"{}\"",
+                    self.name(cave),
+                    self.name(next)
+                )?;
+                if !attributes.is_empty() {
+                    write!(out, " [{}]", attributes.join(", "))?;
+                }
+                writeln!(out, ";")?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+
+    #[allow(dead_code)]
+    pub fn save_dot(&self, path: impl AsRef<Path>, highlight: Option<&[&str]>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_dot(&mut out, highlight)?;
+        out.flush()
+    }
+}
+
+#[allow(dead_code)]
+pub struct Paths<'a> {
+    graph: &'a CaveGraph,
+    rules: Rules,
+    /// The states along the current path, each with the index of its next neighbour to try
+    stack: Vec<(State, usize)>,
 }
 
-pub fn solve(input: &[(String, String)], can_visit_small: fn(&String, &Node) -> bool) -> usize {
-    let mut edges = MultiMap::<String, String>::new();
-    for (left, right) in input.to_vec() {
-        edges.insert(left.clone(), right.clone());
-        edges.insert(right, left);
-    }
-    let mut finished_paths = Vec::<Path>::new();
-    let mut queue = VecDeque::<Node>::new();
-    queue.push_back(Node {
-        path: vec!["start".to_string()],
-        did_revisit_small_cave: false,
-    });
-    while let Some(node) = queue.pop_front() {
-        let current_cave = node.path.last().unwrap();
-        if current_cave == "end" {
-            finished_paths.push(node.path.clone());
-        }
-        for next_cave in edges.get_vec(current_cave).unwrap() {
-            let mut did_revisit_small_cave = node.did_revisit_small_cave;
-            if is_small_cave(next_cave) {
-                if !can_visit_small(next_cave, &node) {
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let graph = self.graph;
+        while let Some((state, next_index)) = self.stack.last_mut() {
+            let next = match graph.neighbours[state.cave].get(*next_index) {
+                Some(&(next, _)) if state.cave != self.rules.end => next,
+                _ => {
+                    // Backtrack
+                    self.stack.pop();
                     continue;
                 }
-                if node.path.contains(next_cave) {
-                    did_revisit_small_cave = true;
+            };
+            *next_index += 1;
+            if let Some(next_state) = graph.visit(&self.rules, state, next) {
+                self.stack.push((next_state, 0));
+                if next == self.rules.end {
+                    let path = self
+                        .stack
+                        .iter()
+                        .map(|(state, _)| graph.name(state.cave))
+                        .collect();
+                    return Some(path);
                 }
             }
-            // Extend current path with next cave
-            let mut new_path = node.path.clone();
-            new_path.push(next_cave.clone());
-            queue.push_back(Node {
-                path: new_path,
-                did_revisit_small_cave,
-            });
         }
+        None
     }
-    finished_paths.len()
 }
 
 fn is_small_cave(cave: &str) -> bool {
@@ -65,27 +404,19 @@ fn is_small_cave(cave: &str) -> bool {
 }
 
 #[aoc(day12, part1)]
-pub fn part1(input: &[(String, String)]) -> usize {
-    solve(input, |next_cave, node| {
-        // Small caves can only be visited once
-        !node.path.contains(next_cave)
-    })
+pub fn part1(input: &[(String, String)]) -> u64 {
+    // Small caves can only be visited once
+    CaveGraph::new(input).count_paths(&VisitPolicy::default())
 }
 
 #[aoc(day12, part2)]
-pub fn part2(input: &[(String, String)]) -> usize {
-    solve(input, |next_cave, node| {
-        // At most one small cave can be visited twice
-        if !node.path.contains(next_cave) {
-            return true;
-        }
-        // start and end can only be visited once
-        if next_cave == "start" || next_cave == "end" {
-            return false;
-        }
-        // Check if we have already visited any small cave more than once
-        !node.did_revisit_small_cave
-    })
+pub fn part2(input: &[(String, String)]) -> u64 {
+    // At most one small cave can be visited twice
+    let policy = VisitPolicy {
+        revisits: 1,
+        ..VisitPolicy::default()
+    };
+    CaveGraph::new(input).count_paths(&policy)
 }
 
 #[cfg(test)]
@@ -115,4 +446,175 @@ b-end"
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 36);
     }
+
+    lazy_static! {
+        static ref LARGER_INPUT: &'static str = r"
+dc-end
+HN-start
+start-kj
+dc-start
+dc-HN
+LN-dc
+HN-end
+kj-sa
+kj-HN
+kj-dc"
+            .trim();
+        static ref LARGEST_INPUT: &'static str = r"
+fs-end
+he-DX
+fs-he
+start-DX
+pj-DX
+end-zg
+zg-sl
+zg-pj
+pj-he
+RW-he
+fs-DX
+pj-RW
+zg-RW
+start-pj
+he-WI
+zg-he
+pj-fs
+start-RW"
+            .trim();
+    }
+
+    fn revisit_once() -> VisitPolicy {
+        VisitPolicy {
+            revisits: 1,
+            ..VisitPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_count_paths() {
+        let graph = CaveGraph::new(&input_generator(&LARGER_INPUT));
+        assert_eq!(graph.count_paths(&VisitPolicy::default()), 19);
+        assert_eq!(graph.count_paths(&revisit_once()), 103);
+        let graph = CaveGraph::new(&input_generator(&LARGEST_INPUT));
+        assert_eq!(graph.count_paths(&VisitPolicy::default()), 226);
+        assert_eq!(graph.count_paths(&revisit_once()), 3509);
+    }
+
+    #[test]
+    fn test_paths() {
+        let graph = CaveGraph::new(&input_generator(&TEST_INPUT));
+        let paths = graph.paths(&VisitPolicy::default()).collect::<Vec<_>>();
+        assert_eq!(paths.len(), 10);
+        assert!(paths.contains(&vec!["start", "A", "b", "A", "c", "A", "end"]));
+        assert!(paths.contains(&vec!["start", "b", "end"]));
+        for input in [*TEST_INPUT, *LARGER_INPUT, *LARGEST_INPUT] {
+            let graph = CaveGraph::new(&input_generator(input));
+            for revisits in 0..=2 {
+                let policy = VisitPolicy {
+                    revisits,
+                    ..VisitPolicy::default()
+                };
+                let paths = graph.paths(&policy).collect::<HashSet<_>>();
+                assert_eq!(paths.len() as u64, graph.count_paths(&policy));
+            }
+        }
+        // Only take what we need
+        let graph = CaveGraph::new(&input_generator(&LARGEST_INPUT));
+        let first = graph.paths(&revisit_once()).next().unwrap();
+        assert_eq!(first.first(), Some(&"start"));
+        assert_eq!(first.last(), Some(&"end"));
+    }
+
+    #[test]
+    fn test_visit_policy() {
+        let graph = CaveGraph::new(&input_generator(&TEST_INPUT));
+        // Forbid c, so A can only lead to b or end
+        let policy = VisitPolicy {
+            limits: [("c".to_string(), 0)].into_iter().collect(),
+            ..VisitPolicy::default()
+        };
+        assert_eq!(graph.count_paths(&policy), 5);
+        // Allowing A only once removes all paths which go back and forth through A
+        let policy = VisitPolicy {
+            limits: [("A".to_string(), 1)].into_iter().collect(),
+            ..VisitPolicy::default()
+        };
+        let paths = graph.paths(&policy).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["start", "A", "b", "end"],
+                vec!["start", "A", "end"],
+                vec!["start", "b", "A", "end"],
+                vec!["start", "b", "end"],
+            ]
+        );
+        // Turn the graph around
+        let policy = VisitPolicy {
+            start: "end".to_string(),
+            end: "start".to_string(),
+            ..VisitPolicy::default()
+        };
+        assert_eq!(graph.count_paths(&policy), 10);
+    }
+
+    #[test]
+    fn test_weighted_paths() {
+        let edges = [
+            ("start", "A", 1),
+            ("A", "b", 5),
+            ("b", "end", 1),
+            ("A", "end", 10),
+        ]
+        .into_iter()
+        .map(|(left, right, weight)| (left.to_string(), right.to_string(), weight))
+        .collect::<Vec<_>>();
+        let graph = CaveGraph::with_weights(&edges);
+        let policy = VisitPolicy::default();
+        assert_eq!(
+            graph.shortest_path(&policy),
+            Some(WeightedPath {
+                weight: 7,
+                caves: vec!["start", "A", "b", "end"]
+            })
+        );
+        assert_eq!(
+            graph.longest_path(&policy),
+            Some(WeightedPath {
+                weight: 21,
+                caves: vec!["start", "A", "b", "A", "end"]
+            })
+        );
+        let policy = VisitPolicy {
+            limits: [("b".to_string(), 0)].into_iter().collect(),
+            ..VisitPolicy::default()
+        };
+        assert_eq!(graph.longest_path(&policy).unwrap().weight, 11);
+        let policy = VisitPolicy {
+            limits: [("A".to_string(), 0)].into_iter().collect(),
+            ..VisitPolicy::default()
+        };
+        assert_eq!(graph.shortest_path(&policy), None);
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let graph = CaveGraph::new(&input_generator("start-A\nA-b\nb-end"));
+        let mut out = Vec::new();
+        graph
+            .write_dot(&mut out, Some(&["start", "A", "b"]))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"graph caves {
+    "start" [shape=ellipse, color=red, penwidth=2];
+    "A" [shape=box, style=filled, fillcolor=lightgrey, color=red, penwidth=2];
+    "b" [shape=ellipse, color=red, penwidth=2];
+    "end" [shape=ellipse];
+    "start" -- "A" [color=red, penwidth=2];
+    "A" -- "b" [color=red, penwidth=2];
+    "b" -- "end";
+}
+"#
+        );
+    }
 }
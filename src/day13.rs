@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 
-use crate::util::Vector2D;
+use crate::util::{recognize, Vector2D};
 
 pub type Paper = HashSet<Vector2D>;
 
@@ -8,6 +8,40 @@ pub type Paper = HashSet<Vector2D>;
 pub enum Fold {
     AlongX(i32),
     AlongY(i32),
+    /// Fold along the line `x + y = c`
+    AlongDiagonal(i32),
+    /// Fold along the line `x - y = c`
+    AlongAntiDiagonal(i32),
+}
+
+impl Fold {
+    /// The fold line is `normal . pos = c`, and dots with `normal . pos > c` are folded over.
+    fn line(self) -> (Vector2D, i32) {
+        match self {
+            Fold::AlongX(c) => (Vector2D::new(1, 0), c),
+            Fold::AlongY(c) => (Vector2D::new(0, 1), c),
+            Fold::AlongDiagonal(c) => (Vector2D::new(1, 1), c),
+            Fold::AlongAntiDiagonal(c) => (Vector2D::new(1, -1), c),
+        }
+    }
+
+    /// The reflection in the fold line, `pos - 2 * (normal . pos - c) / |normal|^2 * normal`.
+    /// Since the normal is either axis-aligned or diagonal, this always maps dots onto dots.
+    fn reflection(self) -> Affine {
+        let (normal, c) = self.line();
+        let norm = dot(normal, normal);
+        let n = [normal.x(), normal.y()];
+        let mut matrix = [[1, 0], [0, 1]];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell -= 2 * n[i] * n[j] / norm;
+            }
+        }
+        Affine {
+            matrix,
+            offset: normal * (2 * c / norm),
+        }
+    }
 }
 
 pub type Input = (Paper, Vec<Fold>);
@@ -30,6 +64,8 @@ pub fn input_generator(input: &str) -> Input {
             match fold_along {
                 "fold along x" => Fold::AlongX(coord),
                 "fold along y" => Fold::AlongY(coord),
+                "fold along x+y" => Fold::AlongDiagonal(coord),
+                "fold along x-y" => Fold::AlongAntiDiagonal(coord),
                 _ => panic!("unexpected input: {}", line),
             }
         })
@@ -41,30 +77,203 @@ fn fold_paper(paper: &Paper, fold: Fold) -> Paper {
     paper.iter().map(|pos| fold_dot(*pos, fold)).collect()
 }
 
-fn fold_dot(mut pos: Vector2D, fold: Fold) -> Vector2D {
-    match fold {
-        Fold::AlongX(fold_x) if pos.x() > fold_x => {
-            *pos.x_mut() = 2 * fold_x - pos.x();
+fn fold_dot(pos: Vector2D, fold: Fold) -> Vector2D {
+    let (normal, c) = fold.line();
+    if dot(normal, pos) > c {
+        fold.reflection().apply(pos)
+    } else {
+        pos
+    }
+}
+
+fn dot(a: Vector2D, b: Vector2D) -> i32 {
+    a.x() * b.x() + a.y() * b.y()
+}
+
+/// An affine map `pos -> matrix * pos + offset`, where the matrix is a signed permutation matrix.
+/// Such a map is a bijection on the grid, and its inverse uses the transposed matrix.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Affine {
+    matrix: [[i32; 2]; 2],
+    offset: Vector2D,
+}
+
+impl Affine {
+    fn identity() -> Self {
+        Affine {
+            matrix: [[1, 0], [0, 1]],
+            offset: Vector2D::zero(),
         }
-        Fold::AlongY(fold_y) if pos.y() > fold_y => {
-            *pos.y_mut() = 2 * fold_y - pos.y();
+    }
+
+    fn linear(&self, pos: Vector2D) -> Vector2D {
+        let [[a, b], [c, d]] = self.matrix;
+        Vector2D::new(a * pos.x() + b * pos.y(), c * pos.x() + d * pos.y())
+    }
+
+    fn linear_transposed(&self, pos: Vector2D) -> Vector2D {
+        let [[a, b], [c, d]] = self.matrix;
+        Vector2D::new(a * pos.x() + c * pos.y(), b * pos.x() + d * pos.y())
+    }
+
+    pub fn apply(&self, pos: Vector2D) -> Vector2D {
+        self.linear(pos) + self.offset
+    }
+
+    pub fn invert(&self, pos: Vector2D) -> Vector2D {
+        self.linear_transposed(pos - self.offset)
+    }
+
+    /// Applies this map first, and then the other map.
+    fn then(&self, other: &Affine) -> Affine {
+        let columns = [
+            other.linear(Vector2D::new(self.matrix[0][0], self.matrix[1][0])),
+            other.linear(Vector2D::new(self.matrix[0][1], self.matrix[1][1])),
+        ];
+        Affine {
+            matrix: [
+                [columns[0].x(), columns[1].x()],
+                [columns[0].y(), columns[1].y()],
+            ],
+            offset: other.apply(self.offset),
         }
-        _ => {}
     }
-    pos
 }
 
-fn print_paper(paper: &Paper) {
-    let width = paper.iter().map(|pos| pos.x()).max().unwrap();
-    let height = paper.iter().map(|pos| pos.y()).max().unwrap();
-    for y in 0..=height {
-        for x in 0..=width {
-            match paper.contains(&Vector2D::new(x, y)) {
-                true => print!("#"),
-                false => print!("."),
-            };
+/// The dots with `normal . pos <= bound`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct HalfPlane {
+    normal: Vector2D,
+    bound: i32,
+}
+
+impl HalfPlane {
+    fn contains(&self, pos: Vector2D) -> bool {
+        dot(self.normal, pos) <= self.bound
+    }
+}
+
+/// An affine map which only applies to the intersection of some half planes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Piece {
+    region: Vec<HalfPlane>,
+    map: Affine,
+}
+
+impl Piece {
+    fn contains(&self, pos: Vector2D) -> bool {
+        self.region
+            .iter()
+            .all(|half_plane| half_plane.contains(pos))
+    }
+
+    /// Restricts the region to a half plane, or returns `None` if the region becomes empty.
+    /// Only parallel half planes are compared, so some empty regions may remain.
+    fn restrict(mut self, half_plane: HalfPlane) -> Option<Piece> {
+        for existing in self.region.iter_mut() {
+            if existing.normal == half_plane.normal {
+                existing.bound = existing.bound.min(half_plane.bound);
+                return Some(self);
+            }
+            if existing.normal == -half_plane.normal && -existing.bound > half_plane.bound {
+                return None;
+            }
+        }
+        self.region.push(half_plane);
+        Some(self)
+    }
+}
+
+/// A piecewise affine map, where the pieces cover the whole plane without overlapping.
+/// A whole sequence of folds composes into a single transform, which maps each dot
+/// straight to its final position, or back to all of its original positions.
+///
+/// Every fold can double the number of pieces, and `apply` checks the pieces one by one,
+/// so for many folds it's much faster to fold the dots one fold at a time (see `fold_all`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Transform {
+    pieces: Vec<Piece>,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            pieces: vec![Piece {
+                region: vec![],
+                map: Affine::identity(),
+            }],
+        }
+    }
+
+    pub fn fold(fold: Fold) -> Self {
+        let (normal, c) = fold.line();
+        Transform {
+            pieces: vec![
+                Piece {
+                    region: vec![HalfPlane { normal, bound: c }],
+                    map: Affine::identity(),
+                },
+                Piece {
+                    region: vec![HalfPlane {
+                        normal: -normal,
+                        bound: -c - 1,
+                    }],
+                    map: fold.reflection(),
+                },
+            ],
         }
-        println!();
+    }
+
+    pub fn from_folds(folds: &[Fold]) -> Self {
+        folds
+            .iter()
+            .fold(Transform::identity(), |transform, &fold| {
+                transform.then(&Transform::fold(fold))
+            })
+    }
+
+    /// Applies this transform first, and then the other transform.
+    pub fn then(&self, other: &Transform) -> Transform {
+        let mut pieces = Vec::new();
+        for first in &self.pieces {
+            'second: for second in &other.pieces {
+                let mut piece = first.clone();
+                // Pull the region of the second piece back through the first map:
+                // normal . (matrix * pos + offset) <= bound
+                // <=> (matrix^T * normal) . pos <= bound - normal . offset
+                for half_plane in &second.region {
+                    let half_plane = HalfPlane {
+                        normal: first.map.linear_transposed(half_plane.normal),
+                        bound: half_plane.bound - dot(half_plane.normal, first.map.offset),
+                    };
+                    piece = match piece.restrict(half_plane) {
+                        Some(piece) => piece,
+                        None => continue 'second,
+                    };
+                }
+                piece.map = first.map.then(&second.map);
+                pieces.push(piece);
+            }
+        }
+        Transform { pieces }
+    }
+
+    pub fn apply(&self, pos: Vector2D) -> Vector2D {
+        let piece = self.pieces.iter().find(|piece| piece.contains(pos));
+        piece.expect("pieces must cover the plane").map.apply(pos)
+    }
+
+    /// Finds all positions which are mapped onto the given position.
+    /// Every piece is a bijection, so each piece contributes at most one position.
+    #[allow(dead_code)]
+    pub fn unfold(&self, pos: Vector2D) -> Vec<Vector2D> {
+        self.pieces
+            .iter()
+            .map(|piece| piece.map.invert(pos))
+            .zip(self.pieces.iter())
+            .filter(|(original, piece)| piece.contains(*original))
+            .map(|(original, _)| original)
+            .collect()
     }
 }
 
@@ -75,13 +284,23 @@ pub fn part1((paper, folds): &Input) -> usize {
 }
 
 #[aoc(day13, part2)]
-pub fn part2((paper, folds): &Input) -> i32 {
-    let mut paper = paper.clone();
-    for fold in folds {
-        paper = fold_paper(&paper, *fold);
-    }
-    print_paper(&paper);
-    0
+pub fn part2((paper, folds): &Input) -> String {
+    let paper = fold_all(paper, folds);
+    recognize(&paper).unwrap()
+}
+
+/// Solves part 2 by composing all folds into a single transform first.
+#[aoc(day13, part2, Transform)]
+pub fn part2_transform((paper, folds): &Input) -> String {
+    let transform = Transform::from_folds(folds);
+    let paper = paper.iter().map(|&pos| transform.apply(pos)).collect();
+    recognize(&paper).unwrap()
+}
+
+fn fold_all(paper: &Paper, folds: &[Fold]) -> Paper {
+    folds
+        .iter()
+        .fold(paper.clone(), |paper, &fold| fold_paper(&paper, fold))
 }
 
 #[cfg(test)]
@@ -122,7 +341,68 @@ fold along x=5"
 
     #[test]
     fn test_part2() {
-        let input = input_generator(&TEST_INPUT);
-        part2(&input);
+        let (paper, folds) = input_generator(&TEST_INPUT);
+        let paper = fold_all(&paper, &folds);
+        // The example folds into a square, which is not a letter
+        let square = (0..5)
+            .flat_map(|i| [(i, 0), (i, 4), (0, i), (4, i)])
+            .map(|(x, y)| Vector2D::new(x, y))
+            .collect::<Paper>();
+        assert_eq!(paper, square);
+        assert!(recognize(&paper).is_err());
+    }
+
+    #[test]
+    fn test_transform() {
+        let (paper, folds) = input_generator(&TEST_INPUT);
+        let transform = Transform::from_folds(&folds);
+        assert_eq!(transform.pieces.len(), 4);
+        let folded = paper
+            .iter()
+            .map(|&pos| transform.apply(pos))
+            .collect::<Paper>();
+        assert_eq!(fold_all(&paper, &folds), folded);
+
+        let folds = [
+            Fold::AlongDiagonal(20),
+            Fold::AlongY(7),
+            Fold::AlongAntiDiagonal(3),
+            Fold::AlongX(5),
+            Fold::AlongDiagonal(6),
+        ];
+        let transform = Transform::from_folds(&folds);
+        for x in -20..20 {
+            for y in -20..20 {
+                let pos = Vector2D::new(x, y);
+                let expected = folds.iter().fold(pos, |pos, &fold| fold_dot(pos, fold));
+                assert_eq!(transform.apply(pos), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unfold() {
+        let (_, folds) = input_generator(&TEST_INPUT);
+        let transform = Transform::from_folds(&folds);
+        let mut originals = transform.unfold(Vector2D::new(0, 0));
+        originals.sort_by_key(|pos| (pos.x(), pos.y()));
+        assert_eq!(
+            originals,
+            vec![
+                Vector2D::new(0, 0),
+                Vector2D::new(0, 14),
+                Vector2D::new(10, 0),
+                Vector2D::new(10, 14)
+            ]
+        );
+        // Dots on a fold line stay where they are
+        assert_eq!(
+            Transform::fold(Fold::AlongDiagonal(4)).unfold(Vector2D::new(1, 3)),
+            vec![Vector2D::new(1, 3)]
+        );
+        assert_eq!(
+            Transform::fold(Fold::AlongDiagonal(4)).unfold(Vector2D::new(1, 1)),
+            vec![Vector2D::new(1, 1), Vector2D::new(3, 3)]
+        );
     }
 }
@@ -1,13 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::ops::{Add, Mul, Range};
 
+pub type Rule = ((char, char), char);
 pub type RuleMap = HashMap<(char, char), char>;
 pub type Input = (Vec<char>, RuleMap);
 
 #[aoc_generator(day14)]
 pub fn input_generator(input: &str) -> Input {
+    parse_input(input)
+        .unwrap_or_else(|validation| panic!("conflicting rules: {:?}", validation.duplicates))
+}
+
+/// Parses and validates the input. Fails if some pair has rules which insert different elements,
+/// since then the `RuleMap` would silently keep only one of them.
+pub fn parse_input(input: &str) -> Result<Input, Validation> {
     let (template, rules) = input.split_once("\n\n").unwrap();
-    let template = template.chars().collect();
-    let rules = rules
+    let template = template.chars().collect::<Vec<_>>();
+    let rules = parse_rules(rules);
+    let validation = validate(&template, &rules);
+    if !validation.duplicates.is_empty() {
+        return Err(validation);
+    }
+    Ok((template, rules.into_iter().collect()))
+}
+
+/// Parses the rules as they are listed, so they can be validated before building a `RuleMap`.
+pub fn parse_rules(rules: &str) -> Vec<Rule> {
+    rules
         .lines()
         .map(|line| {
             let (pair, result) = line.split_once(" -> ").unwrap();
@@ -15,8 +35,7 @@ pub fn input_generator(input: &str) -> Input {
             let result = result.chars().next().unwrap();
             ((left, right), result)
         })
-        .collect();
-    (template, rules)
+        .collect()
 }
 
 fn step(polymer: &[char], rules: &RuleMap) -> Vec<char> {
@@ -51,59 +70,459 @@ pub fn part1(input: &Input) -> u64 {
     solve_part1(input, 10)
 }
 
-type PairCounts = HashMap<(char, char), u64>;
-type ElementCounts = HashMap<char, u64>;
+/// The lengths of the segments that pairs expand into after some number of steps,
+/// counting the left element of the pair but not the right one.
+struct SegmentLengths<'a> {
+    rules: &'a RuleMap,
+    memo: HashMap<(char, char, usize), u64>,
+}
+
+impl<'a> SegmentLengths<'a> {
+    fn new(rules: &'a RuleMap) -> Self {
+        Self {
+            rules,
+            memo: HashMap::new(),
+        }
+    }
 
-fn make_pair_counts(polymer: &[char]) -> PairCounts {
-    let mut counts = PairCounts::new();
-    for pair in polymer.windows(2) {
-        let (left, right) = (pair[0], pair[1]);
-        *counts.entry((left, right)).or_default() += 1;
+    /// Lengths saturate at `u64::MAX`, which is still more than any index we can ask for.
+    fn get(&mut self, left: char, right: char, steps: usize) -> u64 {
+        if steps == 0 {
+            return 1;
+        }
+        let result = match self.rules.get(&(left, right)) {
+            Some(&result) => result,
+            None => return 1,
+        };
+        if let Some(&length) = self.memo.get(&(left, right, steps)) {
+            return length;
+        }
+        let length =
+            self.get(left, result, steps - 1)
+                .saturating_add(self.get(result, right, steps - 1));
+        self.memo.insert((left, right, steps), length);
+        length
     }
-    counts
 }
 
-fn step_part2(counts: &PairCounts, rules: &RuleMap) -> PairCounts {
-    let mut new_counts = PairCounts::new();
-    for (&(left, right), count) in counts {
-        // Each possible pair MUST have a rule
-        let result = *rules.get(&(left, right)).unwrap();
-        *new_counts.entry((left, result)).or_default() += count;
-        *new_counts.entry((result, right)).or_default() += count;
+/// Builds only the part of the polymer within the given index range after the given number of steps.
+/// Segments which lie entirely before the range are skipped using their memoised lengths,
+/// so this only descends into the segments which overlap the range.
+#[allow(dead_code)]
+pub fn polymer_slice((template, rules): &Input, steps: usize, range: Range<u64>) -> String {
+    let mut lengths = SegmentLengths::new(rules);
+    let mut slice = Slice {
+        skip: range.start,
+        take: range.end.saturating_sub(range.start),
+        result: String::new(),
+    };
+    for pair in template.windows(2) {
+        slice.expand(pair[0], pair[1], steps, &mut lengths);
     }
-    new_counts
+    if let Some(&last) = template.last() {
+        slice.push(last);
+    }
+    slice.result
+}
+
+struct Slice {
+    /// How many elements to skip before the range starts
+    skip: u64,
+    /// How many elements are left to take
+    take: u64,
+    result: String,
 }
 
-fn count_elements(pair_counts: &PairCounts, last_element: char) -> ElementCounts {
-    let mut counts = ElementCounts::new();
-    for (&(left, _), count) in pair_counts {
-        // Each element appears in 2 pairs, except for the first and last pair of the polymer
-        // Count only the first element of the pair, and then compensate for the last one
-        *counts.entry(left).or_default() += count;
+impl Slice {
+    fn push(&mut self, element: char) {
+        if self.skip > 0 {
+            self.skip -= 1;
+        } else if self.take > 0 {
+            self.result.push(element);
+            self.take -= 1;
+        }
+    }
+
+    fn expand(&mut self, left: char, right: char, steps: usize, lengths: &mut SegmentLengths) {
+        if self.take == 0 {
+            return;
+        }
+        let length = lengths.get(left, right, steps);
+        if self.skip >= length {
+            self.skip -= length;
+            return;
+        }
+        match lengths.rules.get(&(left, right)) {
+            Some(&result) if steps > 0 => {
+                self.expand(left, result, steps - 1, lengths);
+                self.expand(result, right, steps - 1, lengths);
+            }
+            _ => self.push(left),
+        }
     }
-    // The last element never changes, so we add it separately
-    *counts.entry(last_element).or_default() += 1;
-    counts
 }
 
-fn solve_part2((template, rules): &Input, steps: usize) -> u64 {
-    let mut pair_counts = make_pair_counts(template);
-    for _ in 1..=steps {
-        pair_counts = step_part2(&pair_counts, rules);
+/// Anything which can count pairs, such as `u128` for fast counting
+/// or `BigUint` for counting arbitrarily many steps.
+pub trait Count:
+    Clone
+    + Ord
+    + Debug
+    + From<u8>
+    + for<'a> Add<&'a Self, Output = Self>
+    + for<'a> Mul<&'a Self, Output = Self>
+{
+}
+
+impl<T> Count for T where
+    T: Clone
+        + Ord
+        + Debug
+        + From<u8>
+        + for<'a> Add<&'a T, Output = T>
+        + for<'a> Mul<&'a T, Output = T>
+{
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn identity_matrix<T: Count>(size: usize) -> Matrix<T> {
+    (0..size)
+        .map(|i| (0..size).map(|j| T::from((i == j) as u8)).collect())
+        .collect()
+}
+
+fn multiply<T: Count>(left: &Matrix<T>, right: &Matrix<T>) -> Matrix<T> {
+    let zero = T::from(0);
+    let size = left.len();
+    let mut result = vec![vec![zero.clone(); size]; size];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (k, left_cell) in left[i].iter().enumerate() {
+            // The transition matrix is sparse, so this skips most of the work
+            if *left_cell == zero {
+                continue;
+            }
+            for (cell, right_cell) in row.iter_mut().zip(right[k].iter()) {
+                *cell = cell.clone() + &(left_cell.clone() * right_cell);
+            }
+        }
     }
-    let counts = count_elements(&pair_counts, *template.last().unwrap());
-    let min_count = *counts.values().min().unwrap();
-    let max_count = *counts.values().max().unwrap();
+    result
+}
+
+fn matrix_power<T: Count>(matrix: &Matrix<T>, mut exponent: u64) -> Matrix<T> {
+    let mut result = identity_matrix(matrix.len());
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base);
+        }
+    }
+    result
+}
+
+/// The pair insertion process, with every element interned into an id
+/// and every pair of elements `(left, right)` numbered as `left * elements.len() + right`.
+#[derive(Debug, Clone)]
+pub struct Polymerization<T> {
+    elements: Vec<char>,
+    ids: HashMap<char, usize>,
+    template: Vec<usize>,
+    /// How many of each pair are produced from a single pair in one step.
+    /// Pairs without a rule are left alone.
+    transition: Matrix<T>,
+}
+
+impl<T: Count> Polymerization<T> {
+    pub fn new((template, rules): &Input) -> Self {
+        let mut elements = template.clone();
+        for (&(left, right), &result) in rules {
+            elements.extend([left, right, result]);
+        }
+        elements.sort_unstable();
+        elements.dedup();
+        let ids = elements
+            .iter()
+            .enumerate()
+            .map(|(id, &element)| (element, id))
+            .collect::<HashMap<_, _>>();
+        let n = elements.len();
+        let mut transition = vec![vec![T::from(0); n * n]; n * n];
+        for left in 0..n {
+            for right in 0..n {
+                let pair = left * n + right;
+                match rules.get(&(elements[left], elements[right])) {
+                    Some(result) => {
+                        let result = ids[result];
+                        for next in [left * n + result, result * n + right] {
+                            transition[pair][next] = transition[pair][next].clone() + &T::from(1);
+                        }
+                    }
+                    None => transition[pair][pair] = T::from(1),
+                }
+            }
+        }
+        Polymerization {
+            template: template.iter().map(|element| ids[element]).collect(),
+            elements,
+            ids,
+            transition,
+        }
+    }
+
+    /// Counts the pairs and elements after the given number of steps.
+    /// The transition matrix is raised to the number of steps by repeated squaring,
+    /// so this only needs O(log(steps)) matrix multiplications.
+    pub fn counts_after(&self, steps: u64) -> PolymerCounts<T> {
+        let n = self.elements.len();
+        let mut initial = vec![T::from(0); n * n];
+        for pair in self.template.windows(2) {
+            let index = pair[0] * n + pair[1];
+            initial[index] = initial[index].clone() + &T::from(1);
+        }
+        let matrix = matrix_power(&self.transition, steps);
+        let mut pairs = vec![T::from(0); n * n];
+        for (count, row) in initial.iter().zip(matrix.iter()) {
+            for (pair, cell) in pairs.iter_mut().zip(row.iter()) {
+                *pair = pair.clone() + &(count.clone() * cell);
+            }
+        }
+        // Each element appears in 2 pairs, except for the first and last element of the polymer
+        // Count only the first element of each pair, and then compensate for the last one
+        let mut elements = vec![T::from(0); n];
+        for (index, count) in pairs.iter().enumerate() {
+            elements[index / n] = elements[index / n].clone() + count;
+        }
+        if let Some(&last) = self.template.last() {
+            // The last element never changes, so we add it separately
+            elements[last] = elements[last].clone() + &T::from(1);
+        }
+        PolymerCounts {
+            ids: self.ids.clone(),
+            pairs,
+            elements,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PolymerCounts<T> {
+    ids: HashMap<char, usize>,
+    pairs: Vec<T>,
+    elements: Vec<T>,
+}
+
+impl<T: Count> PolymerCounts<T> {
+    #[allow(dead_code)]
+    pub fn element(&self, element: char) -> T {
+        match self.ids.get(&element) {
+            Some(&id) => self.elements[id].clone(),
+            None => T::from(0),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn pair(&self, left: char, right: char) -> T {
+        match (self.ids.get(&left), self.ids.get(&right)) {
+            (Some(&left), Some(&right)) => self.pairs[left * self.elements.len() + right].clone(),
+            _ => T::from(0),
+        }
+    }
+
+    /// The length of the polymer
+    #[allow(dead_code)]
+    pub fn len(&self) -> T {
+        self.elements
+            .iter()
+            .fold(T::from(0), |total, count| total + count)
+    }
+
+    /// The least and most common elements which appear in the polymer, along with their counts
+    pub fn least_and_most_common(&self) -> Option<((char, T), (char, T))> {
+        let mut present = self
+            .ids
+            .iter()
+            .map(|(&element, &id)| (element, self.elements[id].clone()))
+            .filter(|(_, count)| *count != T::from(0))
+            .collect::<Vec<_>>();
+        present.sort_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(a.cmp(b)));
+        Some((present.first()?.clone(), present.last()?.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Validation {
+    /// Pairs which can appear in the polymer, but have no insertion rule
+    pub missing: Vec<(char, char)>,
+    /// Pairs with rules which insert different elements, along with all of their results
+    pub duplicates: Vec<((char, char), Vec<char>)>,
+    /// Elements which appear in the rules, but never appear in the polymer
+    pub unreachable: Vec<char>,
+}
+
+impl Validation {
+    #[allow(dead_code)]
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.duplicates.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+/// Checks a rule list against a template, following every rule for duplicate pairs.
+/// Repeating the exact same rule is allowed, since that doesn't change the polymer.
+pub fn validate(template: &[char], rules: &[Rule]) -> Validation {
+    let mut results = HashMap::<(char, char), Vec<char>>::new();
+    for &(pair, result) in rules {
+        let pair_results = results.entry(pair).or_default();
+        if !pair_results.contains(&result) {
+            pair_results.push(result);
+        }
+    }
+    // Find all pairs which can appear in the polymer
+    let mut reachable = template
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect::<HashSet<_>>();
+    let mut queue = reachable.iter().copied().collect::<Vec<_>>();
+    while let Some((left, right)) = queue.pop() {
+        for &result in results.get(&(left, right)).into_iter().flatten() {
+            for next in [(left, result), (result, right)] {
+                if reachable.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+    }
+    let mut missing = reachable
+        .iter()
+        .filter(|pair| !results.contains_key(pair))
+        .copied()
+        .collect::<Vec<_>>();
+    missing.sort_unstable();
+    let mut duplicates = results
+        .into_iter()
+        .filter(|(_, results)| results.len() > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort_unstable();
+    let reachable_elements = reachable
+        .iter()
+        .flat_map(|&(left, right)| [left, right])
+        .chain(template.iter().copied())
+        .collect::<HashSet<_>>();
+    let mut unreachable = rules
+        .iter()
+        .flat_map(|&((left, right), result)| [left, right, result])
+        .filter(|element| !reachable_elements.contains(element))
+        .collect::<Vec<_>>();
+    unreachable.sort_unstable();
+    unreachable.dedup();
+    Validation {
+        missing,
+        duplicates,
+        unreachable,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ElementGrowth {
+    pub element: char,
+    /// The factor by which the count of this element grows per step in the long run
+    pub growth: f64,
+    /// The fraction of the polymer made up by this element in the long run
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrowthAnalysis {
+    /// The dominant eigenvalue of the pair transition matrix (restricted to the pairs
+    /// which can appear in the polymer), i.e. the factor by which the polymer grows per step
+    pub dominant_eigenvalue: f64,
+    /// All elements sorted by their share, so the fastest growing elements come first
+    pub elements: Vec<ElementGrowth>,
+}
+
+/// Estimates the long-term growth of the polymer by power iteration:
+/// the pair counts are stepped forward and normalized to sum to 1 after each step,
+/// so they converge towards the dominant eigenvector of the transition matrix.
+#[allow(dead_code)]
+pub fn analyse_growth(input: &Input, iterations: usize) -> GrowthAnalysis {
+    let polymerization = Polymerization::<u64>::new(input);
+    let n = polymerization.elements.len();
+    let transition = polymerization
+        .transition
+        .iter()
+        .map(|row| row.iter().map(|&cell| cell as f64).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut pairs = vec![0.0; n * n];
+    for pair in polymerization.template.windows(2) {
+        pairs[pair[0] * n + pair[1]] += 1.0;
+    }
+    let element_counts = |pairs: &[f64]| {
+        let mut counts = vec![0.0; n];
+        for (index, count) in pairs.iter().enumerate() {
+            counts[index / n] += count;
+        }
+        counts
+    };
+    let mut eigenvalue = 0.0;
+    let mut growth = vec![0.0; n];
+    for _ in 0..iterations {
+        let total = pairs.iter().sum::<f64>();
+        if total == 0.0 {
+            break;
+        }
+        let mut next = vec![0.0; n * n];
+        for (count, row) in pairs.iter().zip(transition.iter()) {
+            if *count != 0.0 {
+                for (next_count, cell) in next.iter_mut().zip(row.iter()) {
+                    *next_count += count * cell;
+                }
+            }
+        }
+        eigenvalue = next.iter().sum::<f64>() / total;
+        let (before, after) = (element_counts(&pairs), element_counts(&next));
+        for (element_growth, (before, after)) in growth.iter_mut().zip(before.iter().zip(after)) {
+            *element_growth = if *before > 0.0 { after / before } else { 0.0 };
+        }
+        let next_total = next.iter().sum::<f64>();
+        pairs = next.into_iter().map(|count| count / next_total).collect();
+    }
+    let shares = element_counts(&pairs);
+    let mut elements = polymerization
+        .elements
+        .iter()
+        .zip(growth.into_iter().zip(shares))
+        .map(|(&element, (growth, share))| ElementGrowth {
+            element,
+            growth,
+            share,
+        })
+        .collect::<Vec<_>>();
+    elements.sort_by(|a, b| b.share.partial_cmp(&a.share).unwrap());
+    GrowthAnalysis {
+        dominant_eigenvalue: eigenvalue,
+        elements,
+    }
+}
+
+fn solve_part2(input: &Input, steps: u64) -> u128 {
+    let counts = Polymerization::<u128>::new(input).counts_after(steps);
+    let ((_, min_count), (_, max_count)) = counts.least_and_most_common().unwrap();
     max_count - min_count
 }
 
 #[aoc(day14, part2)]
-pub fn part2(input: &Input) -> u64 {
+pub fn part2(input: &Input) -> u128 {
     solve_part2(input, 40)
 }
 
 #[cfg(test)]
 mod tests {
+    use num_bigint::BigUint;
+
     use super::*;
 
     lazy_static! {
@@ -146,4 +565,118 @@ CN -> C"
         let input = input_generator(&TEST_INPUT);
         assert_eq!(solve_part2(&input, 10), 1588);
     }
+
+    #[test]
+    fn test_counts_after() {
+        let input = input_generator(&TEST_INPUT);
+        let polymerization = Polymerization::<u128>::new(&input);
+        let counts = polymerization.counts_after(10);
+        assert_eq!(counts.len(), 3073);
+        assert_eq!(counts.element('B'), 1749);
+        assert_eq!(counts.element('C'), 298);
+        assert_eq!(counts.element('H'), 161);
+        assert_eq!(counts.element('N'), 865);
+        assert_eq!(counts.element('X'), 0);
+        let counts = polymerization.counts_after(2);
+        // NNCB -> NCNBCHB -> NBCCNBBBCBHCB
+        assert_eq!(counts.pair('C', 'B'), 2);
+        assert_eq!(counts.pair('B', 'B'), 2);
+        assert_eq!(counts.pair('N', 'N'), 0);
+    }
+
+    #[test]
+    fn test_counts_after_huge() {
+        let input = input_generator(&TEST_INPUT);
+        let small = Polymerization::<u128>::new(&input).counts_after(40);
+        let big = Polymerization::<BigUint>::new(&input).counts_after(40);
+        assert_eq!(BigUint::from(small.element('B')), big.element('B'));
+        // The polymer doubles (minus one) in length every step
+        let input = input_generator("AB\n\nAB -> A\nAA -> B\nBA -> B\nBB -> A");
+        let counts = Polymerization::<BigUint>::new(&input).counts_after(1000);
+        let expected_len = (BigUint::from(1u8) << 1000u32) + BigUint::from(1u8);
+        assert_eq!(counts.len(), expected_len);
+        assert_eq!(counts.element('A') + counts.element('B'), expected_len);
+    }
+
+    #[test]
+    fn test_polymer_slice() {
+        let input = input_generator(&TEST_INPUT);
+        let (template, rules) = &input;
+        assert_eq!(polymer_slice(&input, 0, 0..10), "NNCB");
+        assert_eq!(polymer_slice(&input, 2, 3..7), "CNBB");
+        let mut polymer = template.clone();
+        for _ in 1..=10 {
+            polymer = step(&polymer, rules);
+        }
+        let polymer = polymer.into_iter().collect::<String>();
+        for (start, end) in [(0, 3073), (1000, 1050), (3070, 3080), (4000, 5000)] {
+            let expected = polymer.get(start..end.min(3073)).unwrap_or("");
+            assert_eq!(
+                polymer_slice(&input, 10, start as u64..end as u64),
+                expected
+            );
+        }
+        // Way too long to build in full
+        let start = 1_000_000_000;
+        let slice = polymer_slice(&input, 40, start..start + 50);
+        assert_eq!(slice.len(), 50);
+        assert_eq!(
+            polymer_slice(&input, 40, start + 20..start + 30),
+            slice[20..30]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let input = input_generator(&TEST_INPUT);
+        let (template, rules) = input;
+        let rules = rules.into_iter().collect::<Vec<_>>();
+        assert!(validate(&template, &rules).is_valid());
+
+        let rules = parse_rules("AB -> C\nCB -> A\nCB -> B\nXY -> Z\nAA -> B");
+        assert_eq!(
+            validate(&['A', 'B'], &rules),
+            Validation {
+                missing: vec![('A', 'C'), ('B', 'B'), ('C', 'A')],
+                duplicates: vec![(('C', 'B'), vec!['A', 'B'])],
+                unreachable: vec!['X', 'Y', 'Z'],
+            }
+        );
+
+        let duplicated = format!("{}\nCH -> H", *TEST_INPUT);
+        let validation = parse_input(&duplicated).unwrap_err();
+        assert_eq!(validation.duplicates, vec![(('C', 'H'), vec!['B', 'H'])]);
+        assert_eq!(parse_input(&TEST_INPUT), Ok(input_generator(&TEST_INPUT)));
+        // Repeating a rule is harmless
+        let repeated = format!("{}\nCH -> B", *TEST_INPUT);
+        assert_eq!(parse_input(&repeated), Ok(input_generator(&TEST_INPUT)));
+    }
+
+    #[test]
+    fn test_analyse_growth() {
+        let input = input_generator(&TEST_INPUT);
+        let analysis = analyse_growth(&input, 300);
+        // Every pair has a rule, so the polymer doubles in length every step
+        assert!((analysis.dominant_eigenvalue - 2.0).abs() < 1e-9);
+        // But only B and N keep up, C and H make up a vanishing part of the polymer
+        let elements = analysis
+            .elements
+            .iter()
+            .map(|element| element.element)
+            .collect::<String>();
+        assert_eq!(elements, "BNCH");
+        assert!((analysis.elements[0].share - 2.0 / 3.0).abs() < 1e-9);
+        assert!((analysis.elements[1].growth - 2.0).abs() < 1e-9);
+        assert!(analysis.elements[2].growth < 1.8);
+        assert!(analysis.elements[3].growth < 1.8);
+
+        // AB -> AB + BB and BB -> BA + AB, where BA stays put: the Fibonacci numbers!
+        let input = input_generator("AB\n\nAB -> B\nBB -> A");
+        let analysis = analyse_growth(&input, 100);
+        let golden_ratio = (1.0 + 5f64.sqrt()) / 2.0;
+        assert!((analysis.dominant_eigenvalue - golden_ratio).abs() < 1e-9);
+        for element in analysis.elements {
+            assert!((element.growth - golden_ratio).abs() < 1e-6);
+        }
+    }
 }
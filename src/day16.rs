@@ -22,7 +22,6 @@ pub enum PacketData {
     },
     Operator {
         type_id: OperatorType,
-        length: OperatorLength,
         packets: Vec<Packet>,
     },
 }
@@ -100,11 +99,7 @@ impl Packet {
             OperatorLength::Bits(bits) => Self::parse_sub_packets_by_bits(reader, bits)?,
             OperatorLength::PacketCount(count) => Self::parse_sub_packets_by_count(reader, count)?,
         };
-        Ok(PacketData::Operator {
-            type_id,
-            length,
-            packets,
-        })
+        Ok(PacketData::Operator { type_id, packets })
     }
 
     fn parse_sub_packets_by_bits(
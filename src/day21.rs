@@ -186,7 +186,7 @@ pub fn part2(&(start1, start2): &Input) -> u64 {
         for &(roll, roll_count) in QUANTUM_ROLLS.iter() {
             debug_assert!(roll_count != 0);
             let mut new_state = state.clone();
-            new_state.step(roll as u8);
+            new_state.step(roll);
             let new_state_count = state_count * roll_count;
             // If game is done, stop expanding it.
             if new_state.did_player1_win_part2() {
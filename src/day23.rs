@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 use std::iter::empty;
 
-use itertools::Itertools;
 use pathfinding::prelude::*;
 
 use crate::util::Vector2D;
@@ -107,7 +106,7 @@ impl State {
         &'a self,
         room_kind: AmphipodKind,
         burrow: &'a Burrow,
-    ) -> impl Iterator<Item = &Amphipod> + 'a {
+    ) -> impl Iterator<Item = &'a Amphipod> + 'a {
         burrow
             .tiles
             .iter()
@@ -304,7 +303,7 @@ pub fn part2(input: &str) -> u32 {
     let mut lines = input.lines().collect::<Vec<_>>();
     lines.insert(3, "  #D#C#B#A#");
     lines.insert(4, "  #D#B#A#C#");
-    let input = lines.into_iter().intersperse("\n").collect::<String>();
+    let input = lines.join("\n");
     part1(&input)
 }
 
@@ -38,22 +38,57 @@ pub fn part2(input: &[Vec<bool>]) -> i32 {
 }
 
 fn find_rating(input: &[Vec<bool>], most_common: bool) -> Vec<bool> {
-    let nb_bits = input[0].len();
-    let mut candidates = input.to_vec();
-    for i in 0..nb_bits {
-        let nb_ones = candidates.iter().filter(|number| number[i]).count();
-        let nb_zeros = candidates.len() - nb_ones;
-        let target_bit = if most_common {
+    let steps = filter_rating(input, |_, nb_ones, nb_zeros| {
+        if most_common {
             nb_ones >= nb_zeros
         } else {
             nb_ones < nb_zeros
-        };
-        candidates.retain(|candidate| candidate[i] == target_bit);
-        if candidates.len() == 1 {
-            return candidates.first().unwrap().clone();
         }
+    });
+    match steps.last() {
+        Some(step) if step.candidates.len() == 1 => step.candidates[0].clone(),
+        _ => panic!("no rating found"),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FilterStep {
+    /// The bit position that was considered in this step.
+    pub bit: usize,
+    /// The bit value that candidates needed to have at that position.
+    pub kept_bit: bool,
+    /// The candidates remaining after this step.
+    pub candidates: Vec<Vec<bool>>,
+}
+
+/// Repeatedly filters the candidates on each bit position, from left to right,
+/// until at most one candidate remains.
+///
+/// For every position, `criterion` is called with the position, the number of ones
+/// and the number of zeros among the remaining candidates, and returns the bit value to keep.
+/// Returns the candidates remaining after each step, so the last step holds the rating.
+pub fn filter_rating(
+    input: &[Vec<bool>],
+    criterion: impl Fn(usize, usize, usize) -> bool,
+) -> Vec<FilterStep> {
+    let nb_bits = input.first().map_or(0, |number| number.len());
+    let mut candidates = input.to_vec();
+    let mut steps = Vec::new();
+    for bit in 0..nb_bits {
+        if candidates.len() <= 1 {
+            break;
+        }
+        let nb_ones = candidates.iter().filter(|number| number[bit]).count();
+        let nb_zeros = candidates.len() - nb_ones;
+        let kept_bit = criterion(bit, nb_ones, nb_zeros);
+        candidates.retain(|candidate| candidate[bit] == kept_bit);
+        steps.push(FilterStep {
+            bit,
+            kept_bit,
+            candidates: candidates.clone(),
+        });
     }
-    panic!("no rating found");
+    steps
 }
 
 #[cfg(test)]
@@ -88,4 +123,20 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 230);
     }
+
+    #[test]
+    fn test_filter_rating() {
+        let input = input_generator(&TEST_INPUT);
+        let steps = filter_rating(&input, |_, nb_ones, nb_zeros| nb_ones >= nb_zeros);
+        let nb_candidates = steps
+            .iter()
+            .map(|step| step.candidates.len())
+            .collect::<Vec<_>>();
+        assert_eq!(nb_candidates, vec![7, 4, 3, 2, 1]);
+        assert_eq!(number_from_bits(&steps[4].candidates[0]), 23);
+
+        // Always keeping zeros ends up with the smallest number
+        let steps = filter_rating(&input, |_, _, _| false);
+        assert_eq!(number_from_bits(&steps.last().unwrap().candidates[0]), 2);
+    }
 }
@@ -1,68 +1,127 @@
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::str::FromStr;
 
+/// A set of `(x, y)` cells which must all be marked for a board to win.
+pub type Pattern = Vec<(usize, usize)>;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum WinRule {
+    /// Any complete row
+    Rows,
+    /// Any complete column
+    Columns,
+    /// Either of the two diagonals (only on square boards)
+    Diagonals,
+    /// All four corners
+    FourCorners,
+    /// Every cell on the board
+    FullCard,
+    /// Any of the given patterns
+    Custom(Vec<Pattern>),
+}
+
+/// The rules from the puzzle: complete any row or column.
+pub const STANDARD_RULES: [WinRule; 2] = [WinRule::Rows, WinRule::Columns];
+
+impl WinRule {
+    pub fn patterns(&self, width: usize, height: usize) -> Vec<Pattern> {
+        match self {
+            WinRule::Rows => (0..height)
+                .map(|y| (0..width).map(|x| (x, y)).collect())
+                .collect(),
+            WinRule::Columns => (0..width)
+                .map(|x| (0..height).map(|y| (x, y)).collect())
+                .collect(),
+            WinRule::Diagonals if width == height => vec![
+                (0..width).map(|i| (i, i)).collect(),
+                (0..width).map(|i| (width - 1 - i, i)).collect(),
+            ],
+            WinRule::Diagonals => vec![],
+            WinRule::FourCorners => vec![vec![
+                (0, 0),
+                (width - 1, 0),
+                (0, height - 1),
+                (width - 1, height - 1),
+            ]],
+            WinRule::FullCard => vec![(0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .collect()],
+            WinRule::Custom(patterns) => patterns.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PatternError {
+    /// A pattern without any cells, which would win before any number is drawn
+    Empty,
+    /// A cell outside of the board
+    OutOfBounds(usize, usize),
+}
+
+/// Collects the patterns of all given rules for a board of the given size.
+/// Fails if a (custom) pattern is empty or has a cell outside of the board.
+pub fn rule_patterns(
+    rules: &[WinRule],
+    width: usize,
+    height: usize,
+) -> Result<Vec<Pattern>, PatternError> {
+    rules
+        .iter()
+        .flat_map(|rule| rule.patterns(width, height))
+        .map(|mut pattern| {
+            if pattern.is_empty() {
+                return Err(PatternError::Empty);
+            }
+            if let Some(&(x, y)) = pattern.iter().find(|&&(x, y)| x >= width || y >= height) {
+                return Err(PatternError::OutOfBounds(x, y));
+            }
+            pattern.sort_unstable();
+            pattern.dedup();
+            Ok(pattern)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
-pub struct Board {
-    numbers: [[u8; 5]; 5],
-    marked: [[bool; 5]; 5],
+pub struct Board<T, const W: usize, const H: usize> {
+    numbers: [[T; W]; H],
+    marked: [[bool; W]; H],
 }
 
-impl FromStr for Board {
+impl<T: FromStr, const W: usize, const H: usize> FromStr for Board<T, W, H> {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let numbers = s
+        let rows = s
             .lines()
-            .take(5)
+            .take(H)
             .map(|line| {
                 line.split(' ')
                     .filter(|x| !x.is_empty())
-                    .map(|x| x.parse::<u8>().unwrap())
-                    .collect::<Vec<_>>()
+                    .map(|x| x.parse::<T>().map_err(|_| ()))
+                    .collect::<Result<Vec<_>, _>>()?
                     .try_into()
-                    .unwrap()
+                    .map_err(|_| ())
             })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
+            .collect::<Result<Vec<[T; W]>, _>>()?;
+        let numbers = rows.try_into().map_err(|_| ())?;
         Ok(Board {
             numbers,
-            marked: Default::default(),
+            marked: [[false; W]; H],
         })
     }
 }
 
-impl Board {
-    fn mark(&mut self, number: u8) {
-        for (y, row) in self.numbers.iter().enumerate() {
-            if let Some(x) = row.iter().position(|&n| n == number) {
-                self.marked[y][x] = true;
-                return;
-            }
-        }
-    }
-
-    fn is_winner(&self) -> bool {
-        // Rows
-        let mut rows = self.marked.iter();
-        if rows.any(|row| row.iter().all(|mark| *mark)) {
-            return true;
-        }
-        // Columns
-        for x in 0..self.marked.len() {
-            let mut column = self.marked.iter().map(|row| row[x]);
-            if column.all(|mark| mark) {
-                return true;
-            }
-        }
-        false
-    }
-
-    fn sum_unmarked(&self) -> u32 {
-        let mut sum = 0u32;
+impl<T: Copy + Eq + Into<u64>, const W: usize, const H: usize> Board<T, W, H> {
+    pub fn sum_unmarked(&self) -> u64 {
+        let mut sum = 0u64;
         for (y, row) in self.numbers.iter().enumerate() {
             for (x, number) in row.iter().enumerate() {
                 if !self.marked[y][x] {
-                    sum += *number as u32;
+                    sum += (*number).into();
                 }
             }
         }
@@ -71,51 +130,188 @@ impl Board {
 }
 
 #[derive(Debug)]
-pub struct Input {
-    draw: Vec<u8>,
-    boards: Vec<Board>,
+pub struct Game<T, const W: usize, const H: usize> {
+    draw: Vec<T>,
+    boards: Vec<Board<T, W, H>>,
 }
 
-#[aoc_generator(day4)]
-pub fn input_generator(input: &str) -> Input {
+pub type Input = Game<u8, 5, 5>;
+
+pub fn parse_game<T: FromStr, const W: usize, const H: usize>(input: &str) -> Game<T, W, H> {
     let mut chunks = input.split("\n\n");
     let draw = chunks
         .next()
         .unwrap()
         .split(',')
+        .map(|x| x.parse().ok().unwrap())
+        .collect::<Vec<T>>();
+    let boards = chunks
         .map(|x| x.parse().unwrap())
-        .collect::<Vec<u8>>();
-    let boards = chunks.map(|x| x.parse().unwrap()).collect::<Vec<Board>>();
-    Input { draw, boards }
+        .collect::<Vec<Board<T, W, H>>>();
+    Game { draw, boards }
 }
 
-#[aoc(day4, part1)]
-pub fn part1(input: &Input) -> u32 {
-    let mut boards = input.boards.to_vec();
-    for &number in &input.draw {
-        boards.iter_mut().for_each(|board| board.mark(number));
-        if let Some(board) = boards.iter().find(|board| board.is_winner()) {
-            return (number as u32) * board.sum_unmarked();
+#[aoc_generator(day4)]
+pub fn input_generator(input: &str) -> Input {
+    parse_game(input)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Win<T> {
+    /// Index of the winning board
+    pub board: usize,
+    /// Index of the winning number in the draw
+    pub turn: usize,
+    /// The winning number
+    pub number: T,
+    /// Sum of all unmarked numbers on the board when it won
+    pub sum_unmarked: u64,
+}
+
+impl<T: Copy + Into<u64>> Win<T> {
+    pub fn score(&self) -> u64 {
+        self.number.into() * self.sum_unmarked
+    }
+}
+
+/// Maps every number to the `(board, (x, y))` cells in which it occurs.
+pub type NumberIndex<T> = HashMap<T, Vec<(usize, (usize, usize))>>;
+
+pub fn index_numbers<T: Copy + Eq + Hash, const W: usize, const H: usize>(
+    boards: &[Board<T, W, H>],
+) -> NumberIndex<T> {
+    let mut index = NumberIndex::<T>::new();
+    for (board_index, board) in boards.iter().enumerate() {
+        for (y, row) in board.numbers.iter().enumerate() {
+            for (x, &number) in row.iter().enumerate() {
+                index.entry(number).or_default().push((board_index, (x, y)));
+            }
         }
     }
-    panic!("no winner");
+    index
 }
 
-#[aoc(day4, part2)]
-pub fn part2(input: &Input) -> u32 {
-    let mut boards = input.boards.to_vec();
-    for &number in &input.draw {
-        boards.iter_mut().for_each(|board| board.mark(number));
-        // Remove all boards that have won in this turn
-        while let Some(pos) = boards.iter().position(|board| board.is_winner()) {
-            let board = boards.remove(pos);
-            // If this was the last board, return its score
-            if boards.is_empty() {
-                return (number as u32) * board.sum_unmarked();
+/// Plays the game until all boards have won or all numbers are drawn.
+/// Returns the wins in the order in which the boards won.
+///
+/// Every board keeps a counter of marked cells for each winning pattern,
+/// so each drawn number only touches the cells in which it occurs.
+pub fn play<T: Copy + Eq + Hash + Into<u64>, const W: usize, const H: usize>(
+    game: &Game<T, W, H>,
+    rules: &[WinRule],
+) -> Result<Vec<Win<T>>, PatternError> {
+    let patterns = rule_patterns(rules, W, H)?;
+    let mut cell_patterns = vec![Vec::new(); W * H];
+    for (pattern_index, pattern) in patterns.iter().enumerate() {
+        for &(x, y) in pattern {
+            cell_patterns[y * W + x].push(pattern_index);
+        }
+    }
+    let index = index_numbers(&game.boards);
+    let mut boards = game.boards.to_vec();
+    let mut sums = boards
+        .iter()
+        .map(|board| board.sum_unmarked())
+        .collect::<Vec<_>>();
+    let mut counters = vec![vec![0usize; patterns.len()]; boards.len()];
+    let mut has_won = vec![false; boards.len()];
+    let mut nb_remaining = boards.len();
+    let mut wins = Vec::new();
+    for (turn, &number) in game.draw.iter().enumerate() {
+        let cells = match index.get(&number) {
+            Some(cells) => cells,
+            None => continue,
+        };
+        let mut winners = Vec::new();
+        for &(board, (x, y)) in cells {
+            if has_won[board] || boards[board].marked[y][x] {
+                continue;
+            }
+            boards[board].marked[y][x] = true;
+            sums[board] -= number.into();
+            for &pattern_index in &cell_patterns[y * W + x] {
+                counters[board][pattern_index] += 1;
+                if counters[board][pattern_index] == patterns[pattern_index].len()
+                    && winners.last() != Some(&board)
+                {
+                    winners.push(board);
+                }
             }
         }
+        // Cells are indexed in board order, so winners of the same turn are too
+        for board in winners {
+            has_won[board] = true;
+            nb_remaining -= 1;
+            wins.push(Win {
+                board,
+                turn,
+                number,
+                sum_unmarked: sums[board],
+            });
+        }
+        if nb_remaining == 0 {
+            break;
+        }
     }
-    panic!("no winner");
+    Ok(wins)
+}
+
+/// Finds the same wins as `play`, but computes each board's winning turn
+/// directly from the turn in which each of its numbers is drawn.
+#[allow(dead_code)]
+pub fn find_wins<T: Copy + Eq + Hash + Into<u64>, const W: usize, const H: usize>(
+    game: &Game<T, W, H>,
+    rules: &[WinRule],
+) -> Result<Vec<Win<T>>, PatternError> {
+    let patterns = rule_patterns(rules, W, H)?;
+    let mut turns = HashMap::<T, usize>::new();
+    for (turn, &number) in game.draw.iter().enumerate() {
+        turns.entry(number).or_insert(turn);
+    }
+    let mut wins = game
+        .boards
+        .iter()
+        .enumerate()
+        .filter_map(|(board_index, board)| {
+            let turn_of = |(x, y): (usize, usize)| turns.get(&board.numbers[y][x]).copied();
+            // A pattern is complete once its last cell is drawn
+            let turn = patterns
+                .iter()
+                .filter_map(|pattern| {
+                    pattern
+                        .iter()
+                        .try_fold(0, |last, &cell| Some(last.max(turn_of(cell)?)))
+                })
+                .min()?;
+            let sum_unmarked = board
+                .numbers
+                .iter()
+                .flat_map(|row| row.iter())
+                .filter(|&number| turns.get(number).map_or(true, |&t| t > turn))
+                .map(|&number| number.into())
+                .sum();
+            Some(Win {
+                board: board_index,
+                turn,
+                number: game.draw[turn],
+                sum_unmarked,
+            })
+        })
+        .collect::<Vec<_>>();
+    wins.sort_by_key(|win| win.turn);
+    Ok(wins)
+}
+
+#[aoc(day4, part1)]
+pub fn part1(input: &Input) -> u64 {
+    let wins = play(input, &STANDARD_RULES).unwrap();
+    wins.first().expect("no winner").score()
+}
+
+#[aoc(day4, part2)]
+pub fn part2(input: &Input) -> u64 {
+    let wins = play(input, &STANDARD_RULES).unwrap();
+    wins.last().expect("no winner").score()
 }
 
 #[cfg(test)]
@@ -158,4 +354,83 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 1924);
     }
+
+    #[test]
+    fn test_play_order() {
+        let input = input_generator(&TEST_INPUT);
+        let wins = play(&input, &STANDARD_RULES).unwrap();
+        let order = wins
+            .iter()
+            .map(|win| (win.board, win.number))
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![(2, 24), (0, 16), (1, 13)]);
+        assert_eq!(find_wins(&input, &STANDARD_RULES), Ok(wins));
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let game = parse_game::<u16, 3, 2>(
+            r"
+300,100,600,400,500
+
+100 200 300
+400 500 600
+
+600 500 400
+300 200 100"
+                .trim(),
+        );
+        // Full card never completes, since 200 is never drawn
+        assert_eq!(play(&game, &[WinRule::FullCard]), Ok(vec![]));
+        assert_eq!(find_wins(&game, &[WinRule::FullCard]), Ok(vec![]));
+        let wins = play(&game, &[WinRule::FourCorners]).unwrap();
+        assert_eq!(find_wins(&game, &[WinRule::FourCorners]), Ok(wins.clone()));
+        assert_eq!(
+            wins,
+            vec![
+                Win {
+                    board: 0,
+                    turn: 3,
+                    number: 400,
+                    sum_unmarked: 700
+                },
+                Win {
+                    board: 1,
+                    turn: 3,
+                    number: 400,
+                    sum_unmarked: 700
+                },
+            ]
+        );
+        let pattern = vec![(1, 0), (1, 1)];
+        let wins = play(&game, &[WinRule::Custom(vec![pattern])]).unwrap();
+        assert_eq!(wins.len(), 0);
+
+        let game = parse_game::<u32, 3, 3>(
+            r"
+3,5,1,7
+
+1 2 3
+4 5 6
+7 8 9"
+                .trim(),
+        );
+        let wins = play(&game, &[WinRule::Diagonals]).unwrap();
+        assert_eq!(wins.len(), 1);
+        assert_eq!((wins[0].turn, wins[0].score()), (3, 7 * 29));
+        assert_eq!(find_wins(&game, &[WinRule::Diagonals]), Ok(wins));
+
+        let out_of_bounds = [WinRule::Custom(vec![vec![(0, 0), (3, 0)]])];
+        assert_eq!(
+            play(&game, &out_of_bounds),
+            Err(PatternError::OutOfBounds(3, 0))
+        );
+        assert_eq!(
+            play(&game, &out_of_bounds),
+            find_wins(&game, &out_of_bounds)
+        );
+        let empty = [WinRule::Rows, WinRule::Custom(vec![vec![]])];
+        assert_eq!(play(&game, &empty), Err(PatternError::Empty));
+        assert_eq!(play(&game, &empty), find_wins(&game, &empty));
+    }
 }
@@ -1,11 +1,26 @@
-use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
-use crate::util::Vector2D;
+use crate::util::{
+    bresenham, count_overlapping_points, heat_color, write_pgm, write_ppm, Segment, Vector2D,
+};
+
+pub type VentMap = HashMap<Vector2D, usize>;
+
+/// The top left and bottom right corners (both inclusive) of a region of the vent map.
+pub type Bounds = (Vector2D, Vector2D);
 
 #[derive(Debug, Copy, Clone)]
 pub struct Line(Vector2D, Vector2D);
 
+impl From<Line> for Segment {
+    fn from(Line(start, end): Line) -> Self {
+        Segment::new(start, end)
+    }
+}
+
 #[aoc_generator(day5)]
 pub fn input_generator(input: &str) -> Vec<Line> {
     input
@@ -30,30 +45,131 @@ pub fn input_generator(input: &str) -> Vec<Line> {
         .collect()
 }
 
-fn sign(x: i32) -> i32 {
-    match x.cmp(&0) {
-        Ordering::Equal => 0,
-        Ordering::Greater => 1,
-        Ordering::Less => -1,
-    }
+/// Counts the points covered by at least two lines, with the lines drawn as in `vent_map`.
+fn count_overlaps(lines: &[Line], diagonals: bool) -> usize {
+    let grid = vent_map(lines, diagonals);
+    grid.values().filter(|&&count| count >= 2).count()
 }
 
-fn count_overlaps(lines: &[Line], diagonals: bool) -> usize {
-    let mut grid = HashMap::<Vector2D, usize>::new();
-    for &Line(start, end) in lines {
-        let diff = end - start;
-        if !diagonals && diff.x() != 0 && diff.y() != 0 {
+/// Counts the number of lines covering each point.
+///
+/// Lines are drawn with Bresenham's line algorithm, so a line which isn't horizontal,
+/// vertical or at 45 degrees also covers the grid points closest to it,
+/// not just the grid points exactly on it.
+fn vent_map(lines: &[Line], diagonals: bool) -> VentMap {
+    let mut grid = VentMap::new();
+    for &line in lines {
+        let segment = Segment::from(line);
+        if !diagonals && !segment.is_axis_aligned() {
             continue;
         }
-        let step = Vector2D::new(sign(diff.x()), sign(diff.y()));
-        let mut pos = start;
-        while pos != end {
+        for pos in bresenham(segment.start, segment.end) {
             grid.entry(pos).and_modify(|count| *count += 1).or_insert(1);
-            pos += step;
         }
-        grid.entry(end).and_modify(|count| *count += 1).or_insert(1);
     }
-    grid.values().filter(|&&count| count >= 2).count()
+    grid
+}
+
+/// The region from the origin up to the furthest covered point,
+/// as drawn in the puzzle description.
+fn default_bounds(grid: &VentMap) -> Bounds {
+    grid.keys().fold(
+        (Vector2D::zero(), Vector2D::zero()),
+        |(top_left, bottom_right), pos| {
+            (
+                top_left.zip_with(pos, |a, b| *a.min(b)),
+                bottom_right.zip_with(pos, |a, b| *a.max(b)),
+            )
+        },
+    )
+}
+
+/// Draws the vent map like the puzzle description, with `.` for uncovered points
+/// and the number of overlapping lines otherwise (or `+` for more than 9 lines).
+#[allow(dead_code)]
+fn render_vent_map(grid: &VentMap, bounds: Option<Bounds>) -> String {
+    let (top_left, bottom_right) = bounds.unwrap_or_else(|| default_bounds(grid));
+    let mut result = String::new();
+    for y in top_left.y()..=bottom_right.y() {
+        for x in top_left.x()..=bottom_right.x() {
+            result.push(match grid.get(&Vector2D::new(x, y)).copied().unwrap_or(0) {
+                0 => '.',
+                count @ 1..=9 => char::from_digit(count as u32, 10).unwrap(),
+                _ => '+',
+            });
+        }
+        result.push('\n');
+    }
+    result
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ImageFormat {
+    /// Grayscale PGM image
+    Pgm,
+    /// Colored PPM image
+    Ppm,
+}
+
+/// Writes the vent map as a heat map image, scaled to the highest number of overlapping lines.
+#[allow(dead_code)]
+fn write_heat_map(
+    out: &mut impl Write,
+    grid: &VentMap,
+    bounds: Option<Bounds>,
+    format: ImageFormat,
+) -> io::Result<()> {
+    let (top_left, bottom_right) = bounds.unwrap_or_else(|| default_bounds(grid));
+    let width = (bottom_right.x() - top_left.x() + 1).max(0) as usize;
+    let height = (bottom_right.y() - top_left.y() + 1).max(0) as usize;
+    let max_count = grid.values().copied().max().unwrap_or(0).max(1);
+    let values = (top_left.y()..=bottom_right.y())
+        .flat_map(|y| (top_left.x()..=bottom_right.x()).map(move |x| Vector2D::new(x, y)))
+        .map(|pos| grid.get(&pos).copied().unwrap_or(0) as f64 / max_count as f64)
+        .collect::<Vec<_>>();
+    match format {
+        ImageFormat::Pgm => {
+            let pixels = values
+                .into_iter()
+                .map(|value| (value * 255.0).round() as u8)
+                .collect::<Vec<_>>();
+            write_pgm(out, width, height, &pixels)
+        }
+        ImageFormat::Ppm => {
+            let pixels = values.into_iter().map(heat_color).collect::<Vec<_>>();
+            write_ppm(out, width, height, &pixels)
+        }
+    }
+}
+
+/// Saves the vent map as a heat map image file.
+#[allow(dead_code)]
+fn save_heat_map(
+    path: impl AsRef<Path>,
+    grid: &VentMap,
+    bounds: Option<Bounds>,
+    format: ImageFormat,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_heat_map(&mut out, grid, bounds, format)?;
+    out.flush()
+}
+
+/// Like `count_overlaps`, but computes the intersections between lines analytically
+/// instead of visiting every point.
+///
+/// Unlike `count_overlaps`, lines only cover the grid points exactly on them.
+/// Both agree as long as all lines are horizontal, vertical or at 45 degrees (as in the puzzle),
+/// but may give different results for lines at any other angle.
+#[allow(dead_code)]
+fn count_overlaps_exact(lines: &[Line], diagonals: bool) -> u64 {
+    let segments = lines
+        .iter()
+        .map(|&line| Segment::from(line))
+        .filter(|segment| diagonals || segment.is_axis_aligned())
+        .collect::<Vec<_>>();
+    count_overlapping_points(&segments)
 }
 
 #[aoc(day5, part1)]
@@ -96,4 +212,49 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 12);
     }
+
+    #[test]
+    fn test_render_vent_map() {
+        let input = input_generator(&TEST_INPUT);
+        let grid = vent_map(&input, true);
+        let expected = r"
+1.1....11.
+.111...2..
+..2.1.111.
+...1.2.2..
+.112313211
+...1.2....
+..1...1...
+.1.....1..
+1.......1.
+222111....
+";
+        assert_eq!(render_vent_map(&grid, None), &expected[1..]);
+        let bounds = (Vector2D::new(3, 3), Vector2D::new(6, 4));
+        assert_eq!(render_vent_map(&grid, Some(bounds)), "1.2.\n2313\n");
+    }
+
+    #[test]
+    fn test_write_heat_map() {
+        let input = input_generator(&TEST_INPUT);
+        let grid = vent_map(&input, true);
+        let bounds = (Vector2D::new(4, 4), Vector2D::new(6, 4));
+        let mut out = Vec::new();
+        write_heat_map(&mut out, &grid, Some(bounds), ImageFormat::Pgm).unwrap();
+        assert_eq!(out, b"P5\n3 1\n255\n\xff\x55\xff");
+        let mut out = Vec::new();
+        write_heat_map(&mut out, &grid, None, ImageFormat::Ppm).unwrap();
+        assert_eq!(out.len(), "P6\n10 10\n255\n".len() + 10 * 10 * 3);
+    }
+
+    #[test]
+    fn test_count_overlaps_exact() {
+        let input = input_generator(&TEST_INPUT);
+        assert_eq!(count_overlaps_exact(&input, false), 5);
+        assert_eq!(count_overlaps_exact(&input, true), 12);
+        // Bresenham covers (1, 1), but the line only passes exactly through (0, 0), (2, 1) and (4, 2)
+        let input = input_generator("0,0 -> 4,2\n1,0 -> 1,2");
+        assert_eq!(count_overlaps(&input, true), 1);
+        assert_eq!(count_overlaps_exact(&input, true), 0);
+    }
 }
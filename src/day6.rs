@@ -1,4 +1,29 @@
-use std::iter::repeat;
+use num_bigint::BigUint;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LifecycleParams {
+    /// Timer of a fish right after giving birth
+    pub reset_timer: u8,
+    /// Timer of a newborn fish
+    pub newborn_timer: u8,
+    /// Amount of new fishes born from each fish per cycle
+    pub litter_size: u64,
+    /// Fishes die once they are older than this many days (instead of giving birth again)
+    pub max_age: Option<u32>,
+}
+
+pub const PUZZLE_PARAMS: LifecycleParams = LifecycleParams {
+    reset_timer: 6,
+    newborn_timer: 8,
+    litter_size: 1,
+    max_age: None,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Fish {
+    timer: u8,
+    age: u32,
+}
 
 #[aoc_generator(day6)]
 pub fn input_generator(input: &str) -> Vec<u8> {
@@ -7,53 +32,202 @@ pub fn input_generator(input: &str) -> Vec<u8> {
 
 #[aoc(day6, part1)]
 pub fn part1(input: &[u8]) -> usize {
-    let mut fishes = input.to_vec();
+    let mut fishes = create_fishes(input);
     for _i in 0..80 {
-        step(&mut fishes);
+        step(&mut fishes, &PUZZLE_PARAMS);
     }
     fishes.len()
 }
 
-fn step(fishes: &mut Vec<u8>) {
-    let mut new_fishes = 0usize;
+fn create_fishes(timers: &[u8]) -> Vec<Fish> {
+    timers.iter().map(|&timer| Fish { timer, age: 0 }).collect()
+}
+
+fn step(fishes: &mut Vec<Fish>, params: &LifecycleParams) {
+    let is_dead = |fish: &Fish| params.max_age.map_or(false, |max_age| fish.age > max_age);
+    let mut new_fishes = 0u64;
     for fish in fishes.iter_mut() {
-        *fish = match *fish {
+        fish.age += 1;
+        if is_dead(fish) {
+            continue;
+        }
+        fish.timer = match fish.timer {
             0 => {
-                new_fishes += 1;
-                6
+                new_fishes += params.litter_size;
+                params.reset_timer
             }
             n => n - 1,
         };
     }
-    fishes.extend(repeat(8).take(new_fishes));
+    fishes.retain(|fish| !is_dead(fish));
+    let newborn = Fish {
+        timer: params.newborn_timer,
+        age: 0,
+    };
+    fishes.extend((0..new_fishes).map(|_| newborn));
 }
 
 #[aoc(day6, part2)]
 pub fn part2(input: &[u8]) -> u64 {
-    let mut population = create_population(input);
-    simulate(&mut population, 256);
-    population.iter().sum()
+    let mut population = create_population(input, &PUZZLE_PARAMS);
+    simulate(&mut population, 256, &PUZZLE_PARAMS);
+    population_size(&population)
+}
+
+// Amount of fishes with a given age and timer, as population[age][timer]
+// (The age is only tracked if fishes can die, otherwise all fishes are counted at age 0,
+// so there's only a single array of amounts per timer)
+type Population = Vec<Vec<u64>>;
+
+fn nb_timers(fishes: &[u8], params: &LifecycleParams) -> usize {
+    let max_timer = fishes.iter().copied().max().unwrap_or(0);
+    max_timer.max(params.reset_timer).max(params.newborn_timer) as usize + 1
 }
 
-// Amount of fishes with a given timer
-type Population = [u64; 9];
+fn nb_ages(params: &LifecycleParams) -> usize {
+    params.max_age.map_or(1, |max_age| max_age as usize + 1)
+}
+
+/// The age of a fish on the next day, or `None` if it dies before then.
+fn next_age(age: usize, params: &LifecycleParams) -> Option<usize> {
+    match params.max_age {
+        Some(max_age) if age + 1 > max_age as usize => None,
+        Some(_) => Some(age + 1),
+        None => Some(0),
+    }
+}
 
-fn create_population(fishes: &[u8]) -> Population {
-    let mut population: Population = Population::default();
-    for fish in fishes {
-        population[*fish as usize] += 1;
+fn create_population(fishes: &[u8], params: &LifecycleParams) -> Population {
+    let mut population = vec![vec![0u64; nb_timers(fishes, params)]; nb_ages(params)];
+    for &fish in fishes {
+        population[0][fish as usize] += 1;
     }
     population
 }
 
-fn simulate(population: &mut Population, steps: usize) {
+fn population_size(population: &Population) -> u64 {
+    population.iter().flatten().sum()
+}
+
+/// Panics if the amount of fishes with some age and timer no longer fits in a `u64`.
+fn simulate(population: &mut Population, steps: usize, params: &LifecycleParams) {
+    fn add(total: &mut u64, count: u64) {
+        *total = total.checked_add(count).expect("population overflows u64");
+    }
     for _step in 0..steps {
-        // Fishes with timer > 0 decrease their timer
-        // Fishes with timer 0 spawn new fishes with timer 8
-        population.rotate_left(1);
-        // Fishes with timer 0 spawn reset their own timer to 6
-        // (After rotating, timer 0 ends up at timer 8)
-        population[6] += population[8];
+        let mut next = vec![vec![0u64; population[0].len()]; population.len()];
+        for (age, counts) in population.iter().enumerate() {
+            let next_age = match next_age(age, params) {
+                Some(next_age) => next_age,
+                None => continue,
+            };
+            for (timer, &count) in counts.iter().enumerate() {
+                if timer == 0 {
+                    add(&mut next[next_age][params.reset_timer as usize], count);
+                    let newborns = count
+                        .checked_mul(params.litter_size)
+                        .expect("population overflows u64");
+                    add(&mut next[0][params.newborn_timer as usize], newborns);
+                } else {
+                    add(&mut next[next_age][timer - 1], count);
+                }
+            }
+        }
+        *population = next;
+    }
+}
+
+// Transition from the population on one day to the population on the next day,
+// where matrix[new_state][old_state] is the number of fishes in the new state
+// resulting from a single fish in the old state.
+// The state of a fish is `age * nb_timers + timer`, like a flattened `Population`.
+type Matrix = Vec<Vec<BigUint>>;
+
+fn transition_matrix(nb_ages: usize, nb_timers: usize, params: &LifecycleParams) -> Matrix {
+    let size = nb_ages * nb_timers;
+    let mut matrix = vec![vec![BigUint::default(); size]; size];
+    for age in 0..nb_ages {
+        let next_age = match next_age(age, params) {
+            Some(next_age) => next_age,
+            None => continue,
+        };
+        let state = |age: usize, timer: u8| age * nb_timers + timer as usize;
+        for timer in 0..nb_timers as u8 {
+            if timer == 0 {
+                // Fishes with timer 0 reset their own timer, and spawn new fishes
+                matrix[state(next_age, params.reset_timer)][state(age, 0)] += 1u8;
+                matrix[state(0, params.newborn_timer)][state(age, 0)] += params.litter_size;
+            } else {
+                // Fishes with timer > 0 decrease their timer
+                matrix[state(next_age, timer - 1)][state(age, timer)] += 1u8;
+            }
+        }
+    }
+    matrix
+}
+
+fn identity_matrix(size: usize) -> Matrix {
+    let mut matrix = vec![vec![BigUint::default(); size]; size];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        row[i] = BigUint::from(1u8);
+    }
+    matrix
+}
+
+fn multiply(left: &Matrix, right: &Matrix, modulus: Option<&BigUint>) -> Matrix {
+    let size = left.len();
+    let mut result = vec![vec![BigUint::default(); size]; size];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..size).map(|k| &left[i][k] * &right[k][j]).sum();
+            if let Some(modulus) = modulus {
+                *cell %= modulus;
+            }
+        }
+    }
+    result
+}
+
+fn matrix_power(matrix: &Matrix, mut exponent: u64, modulus: Option<&BigUint>) -> Matrix {
+    let mut result = identity_matrix(matrix.len());
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base, modulus);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base, modulus);
+        }
+    }
+    result
+}
+
+/// Computes the amount of fishes after the given number of days, optionally modulo some number.
+/// The transition matrix is raised to the number of days by repeated squaring,
+/// so this only needs O(log(days)) matrix multiplications.
+///
+/// If fishes can die, the matrix tracks every combination of age and timer,
+/// so it grows quadratically with `max_age`.
+#[allow(dead_code)]
+pub fn population_after(
+    fishes: &[u8],
+    days: u64,
+    params: &LifecycleParams,
+    modulus: Option<&BigUint>,
+) -> BigUint {
+    let population = create_population(fishes, params);
+    let matrix = transition_matrix(population.len(), population[0].len(), params);
+    let matrix = matrix_power(&matrix, days, modulus);
+    let population = population.into_iter().flatten().collect::<Vec<_>>();
+    let total = matrix
+        .iter()
+        .flat_map(|row| row.iter().zip(population.iter()))
+        .map(|(cell, &count)| cell * count)
+        .sum::<BigUint>();
+    match modulus {
+        Some(modulus) => total % modulus,
+        None => total,
     }
 }
 
@@ -67,13 +241,13 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let mut fishes = input_generator(&TEST_INPUT);
+        let mut fishes = create_fishes(&input_generator(&TEST_INPUT));
         for _i in 0..18 {
-            step(&mut fishes);
+            step(&mut fishes, &PUZZLE_PARAMS);
         }
         assert_eq!(fishes.len(), 26);
         for _i in 18..80 {
-            step(&mut fishes);
+            step(&mut fishes, &PUZZLE_PARAMS);
         }
         assert_eq!(fishes.len(), 5934);
     }
@@ -81,18 +255,92 @@ mod tests {
     #[test]
     fn test_part1_optimized() {
         let fishes = input_generator(&TEST_INPUT);
-        let mut population = create_population(&fishes);
-        simulate(&mut population, 18);
-        assert_eq!(population.iter().sum::<u64>(), 26);
-        simulate(&mut population, 80 - 18);
-        assert_eq!(population.iter().sum::<u64>(), 5934);
+        let mut population = create_population(&fishes, &PUZZLE_PARAMS);
+        simulate(&mut population, 18, &PUZZLE_PARAMS);
+        assert_eq!(population_size(&population), 26);
+        simulate(&mut population, 80 - 18, &PUZZLE_PARAMS);
+        assert_eq!(population_size(&population), 5934);
     }
 
     #[test]
     fn test_part2() {
         let fishes = input_generator(&TEST_INPUT);
-        let mut population = create_population(&fishes);
-        simulate(&mut population, 256);
-        assert_eq!(population.iter().sum::<u64>(), 26984457539);
+        let mut population = create_population(&fishes, &PUZZLE_PARAMS);
+        simulate(&mut population, 256, &PUZZLE_PARAMS);
+        assert_eq!(population_size(&population), 26984457539);
+    }
+
+    #[test]
+    fn test_lifecycle_params() {
+        let fishes = input_generator(&TEST_INPUT);
+        let all_params = [
+            PUZZLE_PARAMS,
+            LifecycleParams {
+                reset_timer: 4,
+                newborn_timer: 7,
+                litter_size: 2,
+                max_age: None,
+            },
+            LifecycleParams {
+                reset_timer: 3,
+                newborn_timer: 5,
+                litter_size: 3,
+                max_age: Some(10),
+            },
+        ];
+        for params in all_params {
+            let mut naive = create_fishes(&fishes);
+            let mut population = create_population(&fishes, &params);
+            for _i in 0..40 {
+                step(&mut naive, &params);
+                simulate(&mut population, 1, &params);
+                assert_eq!(naive.len() as u64, population_size(&population));
+            }
+            assert_eq!(
+                population_after(&fishes, 40, &params, None),
+                BigUint::from(naive.len())
+            );
+        }
+
+        // Every fish dies after one day, before giving birth
+        let params = LifecycleParams {
+            max_age: Some(0),
+            ..PUZZLE_PARAMS
+        };
+        let mut population = create_population(&fishes, &params);
+        simulate(&mut population, 1, &params);
+        assert_eq!(population_size(&population), 0);
+        assert_eq!(
+            population_after(&fishes, 1, &params, None),
+            BigUint::default()
+        );
+    }
+
+    #[test]
+    fn test_population_after() {
+        let fishes = input_generator(&TEST_INPUT);
+        assert_eq!(
+            population_after(&fishes, 0, &PUZZLE_PARAMS, None),
+            BigUint::from(5u8)
+        );
+        assert_eq!(
+            population_after(&fishes, 80, &PUZZLE_PARAMS, None),
+            BigUint::from(5934u64)
+        );
+        assert_eq!(
+            population_after(&fishes, 256, &PUZZLE_PARAMS, None),
+            BigUint::from(26984457539u64)
+        );
+        // Way beyond u64
+        let modulus = BigUint::from(1_000_000_007u64);
+        let population = population_after(&fishes, 2000, &PUZZLE_PARAMS, None);
+        assert!(population.bits() > 64);
+        assert_eq!(
+            population_after(&fishes, 2000, &PUZZLE_PARAMS, Some(&modulus)),
+            population % &modulus
+        );
+        assert!(
+            population_after(&fishes, 1_000_000_000_000, &PUZZLE_PARAMS, Some(&modulus)) < modulus
+        );
     }
 }
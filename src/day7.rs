@@ -4,30 +4,85 @@ pub fn input_generator(input: &str) -> Vec<i32> {
 }
 
 #[aoc(day7, part1)]
-pub fn part1(input: &[i32]) -> i32 {
-    solve(input, |cur_pos, target_pos| (cur_pos - target_pos).abs())
+pub fn part1(input: &[i32]) -> i64 {
+    align_linear(&unweighted(input)).fuel
 }
 
 #[aoc(day7, part2)]
-pub fn part2(input: &[i32]) -> i32 {
-    solve(input, |cur_pos, target_pos| {
-        let steps = (cur_pos - target_pos).abs();
-        // 1 + 2 + 3 + ... + steps = steps * (steps + 1) / 2
-        steps * (steps + 1) / 2
-    })
-}
-
-fn solve(crabs: &[i32], fuel_fn: fn(cur_pos: i32, target_pos: i32) -> i32) -> i32 {
-    let min_pos = *crabs.iter().min().unwrap();
-    let max_pos = *crabs.iter().max().unwrap();
-    let mut best_fuel = i32::MAX;
+pub fn part2(input: &[i32]) -> i64 {
+    align_triangular(&unweighted(input)).fuel
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Crab {
+    pub position: i32,
+    /// Multiplier for the fuel spent by this crab
+    pub weight: i64,
+}
+
+fn unweighted(positions: &[i32]) -> Vec<Crab> {
+    positions
+        .iter()
+        .map(|&position| Crab {
+            position,
+            weight: 1,
+        })
+        .collect()
+}
+
+pub fn linear_fuel(cur_pos: i32, target_pos: i32) -> i64 {
+    (cur_pos as i64 - target_pos as i64).abs()
+}
+
+pub fn triangular_fuel(cur_pos: i32, target_pos: i32) -> i64 {
+    let steps = linear_fuel(cur_pos, target_pos);
+    // 1 + 2 + 3 + ... + steps = steps * (steps + 1) / 2
+    steps * (steps + 1) / 2
+}
+
+#[allow(dead_code)]
+pub fn quadratic_fuel(cur_pos: i32, target_pos: i32) -> i64 {
+    linear_fuel(cur_pos, target_pos).pow(2)
+}
+
+/// Limits the fuel that a single crab can spend.
+/// Note that this is usually no longer convex, so it cannot be used with `align_convex`.
+#[allow(dead_code)]
+pub fn capped(fuel_fn: impl Fn(i32, i32) -> i64, cap: i64) -> impl Fn(i32, i32) -> i64 {
+    move |cur_pos, target_pos| fuel_fn(cur_pos, target_pos).min(cap)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Alignment {
+    pub position: i32,
+    pub fuel: i64,
+}
+
+fn total_fuel(crabs: &[Crab], pos: i32, fuel_fn: &impl Fn(i32, i32) -> i64) -> i64 {
+    crabs
+        .iter()
+        .map(|crab| crab.weight * fuel_fn(crab.position, pos))
+        .sum()
+}
+
+fn position_range(crabs: &[Crab]) -> (i32, i32) {
+    let min_pos = crabs.iter().map(|crab| crab.position).min().unwrap();
+    let max_pos = crabs.iter().map(|crab| crab.position).max().unwrap();
+    (min_pos, max_pos)
+}
+
+/// Tries every position, for cross-checking the faster optimizers.
+#[cfg(test)]
+fn solve(crabs: &[Crab], fuel_fn: impl Fn(i32, i32) -> i64) -> i64 {
+    let (min_pos, max_pos) = position_range(crabs);
+    let mut best_fuel = i64::MAX;
     'outer: for pos in min_pos..=max_pos {
         let mut fuel = 0;
-        for &crab in crabs {
+        for crab in crabs {
             if fuel >= best_fuel {
                 continue 'outer;
             }
-            fuel += fuel_fn(crab, pos);
+            fuel += crab.weight * fuel_fn(crab.position, pos);
         }
         if fuel < best_fuel {
             best_fuel = fuel;
@@ -36,6 +91,74 @@ fn solve(crabs: &[i32], fuel_fn: fn(cur_pos: i32, target_pos: i32) -> i32) -> i3
     best_fuel
 }
 
+/// Computes the total fuel for every position between the leftmost and rightmost crab.
+#[allow(dead_code)]
+pub fn cost_curve(crabs: &[Crab], fuel_fn: impl Fn(i32, i32) -> i64) -> Vec<Alignment> {
+    let (min_pos, max_pos) = position_range(crabs);
+    (min_pos..=max_pos)
+        .map(|position| Alignment {
+            position,
+            fuel: total_fuel(crabs, position, &fuel_fn),
+        })
+        .collect()
+}
+
+/// With linear fuel, the (weighted) median minimizes the sum of distances.
+pub fn align_linear(crabs: &[Crab]) -> Alignment {
+    let mut crabs = crabs.to_vec();
+    crabs.sort_unstable_by_key(|crab| crab.position);
+    let total_weight = crabs.iter().map(|crab| crab.weight).sum::<i64>();
+    let mut weight = 0;
+    let median = crabs
+        .iter()
+        .find(|crab| {
+            weight += crab.weight;
+            weight * 2 >= total_weight
+        })
+        .unwrap();
+    Alignment {
+        position: median.position,
+        fuel: total_fuel(&crabs, median.position, &linear_fuel),
+    }
+}
+
+/// With triangular fuel, the optimal position lies within half a step of the (weighted) mean.
+pub fn align_triangular(crabs: &[Crab]) -> Alignment {
+    let sum = crabs
+        .iter()
+        .map(|crab| crab.weight * crab.position as i64)
+        .sum::<i64>();
+    let total_weight = crabs.iter().map(|crab| crab.weight).sum::<i64>();
+    let mean = sum.div_euclid(total_weight) as i32;
+    (mean - 1..=mean + 1)
+        .map(|position| Alignment {
+            position,
+            fuel: total_fuel(crabs, position, &triangular_fuel),
+        })
+        .min_by_key(|alignment| alignment.fuel)
+        .unwrap()
+}
+
+/// Finds the optimal position for any fuel function which is convex in the target position,
+/// using a ternary search over the range of crab positions.
+#[allow(dead_code)]
+pub fn align_convex(crabs: &[Crab], fuel_fn: impl Fn(i32, i32) -> i64) -> Alignment {
+    let (mut low, mut high) = position_range(crabs);
+    // On integers, ternary search boils down to a binary search for where the total fuel stops decreasing
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if total_fuel(crabs, mid, &fuel_fn) <= total_fuel(crabs, mid + 1, &fuel_fn) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Alignment {
+        position: low,
+        fuel: total_fuel(crabs, low, &fuel_fn),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +176,97 @@ mod tests {
         let input = input_generator(TEST_INPUT);
         assert_eq!(part2(&input), 168);
     }
+
+    #[test]
+    fn test_align() {
+        let input = unweighted(&input_generator(TEST_INPUT));
+        assert_eq!(
+            align_linear(&input),
+            Alignment {
+                position: 2,
+                fuel: 37
+            }
+        );
+        assert_eq!(
+            align_triangular(&input),
+            Alignment {
+                position: 5,
+                fuel: 168
+            }
+        );
+        assert_eq!(align_convex(&input, linear_fuel).fuel, 37);
+        assert_eq!(
+            align_convex(&input, triangular_fuel),
+            align_triangular(&input)
+        );
+        assert_eq!(
+            align_convex(&input, quadratic_fuel).fuel,
+            solve(&input, quadratic_fuel)
+        );
+    }
+
+    #[test]
+    fn test_align_large() {
+        let crabs = (0..1_000_000)
+            .map(|i: i64| ((i * 7919) % 100_003 - 50_000) as i32)
+            .collect::<Vec<_>>();
+        let crabs = unweighted(&crabs);
+        let triangular = align_triangular(&crabs);
+        assert_eq!(align_convex(&crabs, triangular_fuel), triangular);
+        // Spread out over a wide range, with a few far outliers
+        let crabs = crabs
+            .into_iter()
+            .map(|crab| crab.position * 10_000)
+            .chain([1_000_000_000, -1_000_000_000])
+            .collect::<Vec<_>>();
+        let crabs = unweighted(&crabs);
+        let linear = align_linear(&crabs);
+        assert_eq!(align_convex(&crabs, linear_fuel).fuel, linear.fuel);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let crabs = [(0, 1), (10, 3), (4, 1)]
+            .into_iter()
+            .map(|(position, weight)| Crab { position, weight })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            align_linear(&crabs),
+            Alignment {
+                position: 10,
+                fuel: 16
+            }
+        );
+        assert_eq!(
+            align_triangular(&crabs).fuel,
+            solve(&crabs, triangular_fuel)
+        );
+        // Weights behave like duplicate crabs
+        let duplicated = unweighted(&[0, 10, 10, 10, 4]);
+        assert_eq!(
+            cost_curve(&crabs, triangular_fuel),
+            cost_curve(&duplicated, triangular_fuel)
+        );
+    }
+
+    #[test]
+    fn test_cost_curve() {
+        let crabs = unweighted(&[0, 3]);
+        let curve = cost_curve(&crabs, capped(linear_fuel, 2))
+            .into_iter()
+            .map(|alignment| alignment.fuel)
+            .collect::<Vec<_>>();
+        assert_eq!(curve, vec![2, 3, 3, 2]);
+        let speed = 3;
+        let curve = cost_curve(&crabs, |cur_pos, target_pos| {
+            speed * linear_fuel(cur_pos, target_pos)
+        });
+        assert_eq!(
+            curve[1],
+            Alignment {
+                position: 1,
+                fuel: 9
+            }
+        );
+    }
 }
@@ -1,8 +1,102 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::ops::{BitAnd, BitOr, Not, Sub};
+use std::str::FromStr;
 
-type SegmentDisplay = HashSet<char>;
+use lazy_static::*;
 
-#[derive(Debug, Clone)]
+/// The segments of a seven-segment display (`a` through `g`), one bit per segment.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SegmentDisplay(u8);
+
+#[allow(dead_code)]
+impl SegmentDisplay {
+    const ALL: SegmentDisplay = SegmentDisplay(0b0111_1111);
+
+    fn bit(segment: char) -> Option<u8> {
+        match segment {
+            'a'..='g' => Some(1 << (segment as u8 - b'a')),
+            _ => None,
+        }
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, segment: char) -> bool {
+        Self::bit(segment).map_or(false, |bit| self.0 & bit != 0)
+    }
+
+    pub fn is_superset(self, other: SegmentDisplay) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn segments(self) -> impl Iterator<Item = char> {
+        ('a'..='g').filter(move |&segment| self.contains(segment))
+    }
+
+    fn to_set(self) -> SegmentSet {
+        self.segments().collect()
+    }
+}
+
+impl FromStr for SegmentDisplay {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .try_fold(0, |mask, segment| Some(mask | Self::bit(segment)?))
+            .map(SegmentDisplay)
+            .ok_or(())
+    }
+}
+
+impl Display for SegmentDisplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.segments()
+            .try_for_each(|segment| write!(f, "{}", segment))
+    }
+}
+
+impl BitAnd for SegmentDisplay {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        SegmentDisplay(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for SegmentDisplay {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        SegmentDisplay(self.0 | rhs.0)
+    }
+}
+
+impl Sub for SegmentDisplay {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        SegmentDisplay(self.0 & !rhs.0)
+    }
+}
+
+/// Complement within the seven segments of the puzzle's display.
+impl Not for SegmentDisplay {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::ALL - self
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct Entry {
     patterns: [SegmentDisplay; 10],
     output: [SegmentDisplay; 4],
@@ -16,13 +110,13 @@ pub fn input_generator(input: &str) -> Vec<Entry> {
             let (patterns, output) = line.split_once(" | ").unwrap();
             let patterns = patterns
                 .split(' ')
-                .map(|pattern| pattern.chars().collect())
+                .map(|pattern| pattern.parse().unwrap())
                 .collect::<Vec<_>>()
                 .try_into()
                 .unwrap();
             let output = output
                 .split(' ')
-                .map(|pattern| pattern.chars().collect())
+                .map(|pattern| pattern.parse().unwrap())
                 .collect::<Vec<_>>()
                 .try_into()
                 .unwrap();
@@ -46,95 +140,300 @@ pub fn part1(input: &[Entry]) -> usize {
 }
 
 #[aoc(day8, part2)]
-pub fn part2(input: &[Entry]) -> i32 {
-    input.iter().map(decode).sum()
+pub fn part2(input: &[Entry]) -> u32 {
+    input.iter().map(|entry| decode_fast(entry).unwrap()).sum()
 }
 
+/// Solves part 2 with the general wiring search instead of the lookup table.
+#[aoc(day8, part2, Search)]
+pub fn part2_search(input: &[Entry]) -> u64 {
+    input
+        .iter()
+        .map(|entry| {
+            let patterns = entry.patterns.map(SegmentDisplay::to_set);
+            let output = entry.output.map(SegmentDisplay::to_set);
+            decode_with(&SEVEN_SEGMENT, &patterns, &output).unwrap()
+        })
+        .sum()
+}
+
+const DIGITS: [SegmentDisplay; 10] = [
+    SegmentDisplay(0b111_0111), // abcefg
+    SegmentDisplay(0b010_0100), // cf
+    SegmentDisplay(0b101_1101), // acdeg
+    SegmentDisplay(0b110_1101), // acdfg
+    SegmentDisplay(0b010_1110), // bcdf
+    SegmentDisplay(0b110_1011), // abdfg
+    SegmentDisplay(0b111_1011), // abdefg
+    SegmentDisplay(0b010_0101), // acf
+    SegmentDisplay(0b111_1111), // abcdefg
+    SegmentDisplay(0b110_1111), // abcdfg
+];
+
+/// Counts in how many digits each segment is lit.
+const fn segment_counts(patterns: &[SegmentDisplay; 10]) -> [u8; 7] {
+    let mut counts = [0u8; 7];
+    let mut i = 0;
+    while i < patterns.len() {
+        let mut segment = 0;
+        while segment < 7 {
+            if patterns[i].0 & (1 << segment) != 0 {
+                counts[segment] += 1;
+            }
+            segment += 1;
+        }
+        i += 1;
+    }
+    counts
+}
+
+const fn score(display: SegmentDisplay, counts: &[u8; 7]) -> usize {
+    let mut score = 0;
+    let mut segment = 0;
+    while segment < 7 {
+        if display.0 & (1 << segment) != 0 {
+            score += counts[segment] as usize;
+        }
+        segment += 1;
+    }
+    score
+}
+
+/// Maps the score of a display to its digit.
+///
+/// Every wire lights up in the same number of patterns as the segment it's connected to,
+/// no matter how the wires are scrambled. Summing those counts over a display's wires
+/// gives a score which happens to be different for each digit.
+/// (Each of the 7 segments is lit in at most 10 patterns, so scores never exceed 70.)
+const SCORE_LOOKUP: [Option<u8>; 71] = {
+    let counts = segment_counts(&DIGITS);
+    let mut lookup = [None; 71];
+    let mut digit = 0;
+    while digit < DIGITS.len() {
+        lookup[score(DIGITS[digit], &counts)] = Some(digit as u8);
+        digit += 1;
+    }
+    lookup
+};
+
+/// Decodes the output value of an entry from the puzzle using a lookup table.
+/// Returns `None` if the patterns don't score as ten different digits,
+/// or if an output pattern isn't the pattern of the digit it scores as.
+pub fn decode_fast(entry: &Entry) -> Option<u32> {
+    let counts = segment_counts(&entry.patterns);
+    let mut by_digit = [None; 10];
+    for &pattern in &entry.patterns {
+        let digit = SCORE_LOOKUP[score(pattern, &counts)]?;
+        if by_digit[digit as usize].replace(pattern).is_some() {
+            return None;
+        }
+    }
+    entry.output.iter().try_fold(0, |value, &display| {
+        let digit = SCORE_LOOKUP[score(display, &counts)]?;
+        if by_digit[digit as usize] != Some(display) {
+            return None;
+        }
+        Some(value * 10 + digit as u32)
+    })
+}
+
+// The general solver below works with sets of segment names, so it supports any number of segments
+type SegmentSet = HashSet<char>;
+
+/// Describes which segments of a display are lit for each digit.
+#[derive(Debug, Clone)]
+pub struct DisplayTable {
+    segments: Vec<char>,
+    digits: Vec<SegmentSet>,
+}
+
+impl DisplayTable {
+    pub fn new(digits: &[&str]) -> Self {
+        let digits = digits
+            .iter()
+            .map(|digit| digit.chars().collect::<SegmentSet>())
+            .collect::<Vec<_>>();
+        let mut segments = digits.iter().flatten().copied().collect::<Vec<_>>();
+        segments.sort_unstable();
+        segments.dedup();
+        Self { segments, digits }
+    }
+
+    fn find_digit(&self, display: &SegmentSet) -> Option<usize> {
+        self.digits.iter().position(|digit| digit == display)
+    }
+}
+
+lazy_static! {
+    static ref SEVEN_SEGMENT: DisplayTable = DisplayTable::new(&[
+        "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
+    ]);
+}
+
+/// Maps each (scrambled) wire to the segment it's connected to.
+pub type Wiring = HashMap<char, char>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    /// No wiring can produce the given patterns.
+    Inconsistent,
+    /// Multiple wirings can produce the given patterns.
+    Ambiguous,
+    /// An output pattern doesn't correspond to any digit.
+    UnknownDigit,
+}
+
+#[cfg(test)]
 fn decode(entry: &Entry) -> i32 {
-    let Entry { patterns, output } = entry.clone();
+    let patterns = entry.patterns.map(SegmentDisplay::to_set);
+    let output = entry.output.map(SegmentDisplay::to_set);
+    decode_with(&SEVEN_SEGMENT, &patterns, &output).unwrap() as i32
+}
 
-    let pattern1 = patterns
-        .iter()
-        .find(|pattern| pattern.len() == 2)
-        .unwrap()
-        .clone();
-    let pattern4 = patterns
-        .iter()
-        .find(|pattern| pattern.len() == 4)
-        .unwrap()
-        .clone();
-    let pattern7 = patterns
+/// Decodes an output value, with one digit per output pattern
+/// in the base given by the number of digits in the table.
+pub fn decode_with(
+    table: &DisplayTable,
+    patterns: &[SegmentSet],
+    output: &[SegmentSet],
+) -> Result<u64, DecodeError> {
+    let wiring = solve_wiring(table, patterns)?;
+    let mut value = 0;
+    for output_display in output {
+        let digit = rewire(output_display, &wiring)
+            .and_then(|display| table.find_digit(&display))
+            .ok_or(DecodeError::UnknownDigit)?;
+        value = value * table.digits.len() as u64 + digit as u64;
+    }
+    Ok(value)
+}
+
+/// Finds the wiring for which every pattern lights up a different digit.
+pub fn solve_wiring(table: &DisplayTable, patterns: &[SegmentSet]) -> Result<Wiring, DecodeError> {
+    let domains = table
+        .segments
         .iter()
-        .find(|pattern| pattern.len() == 3)
-        .unwrap()
-        .clone();
-    let pattern8 = patterns
+        .map(|&wire| (wire, table.segments.iter().copied().collect()))
+        .collect::<Domains>();
+    let mut solutions = Vec::new();
+    search(table, patterns, domains, &mut solutions);
+    match solutions.len() {
+        0 => Err(DecodeError::Inconsistent),
+        1 => Ok(solutions.pop().unwrap()),
+        _ => Err(DecodeError::Ambiguous),
+    }
+}
+
+/// Returns `None` if the display uses a wire which isn't part of the wiring.
+fn rewire(display: &SegmentSet, wiring: &Wiring) -> Option<SegmentSet> {
+    display
         .iter()
-        .find(|pattern| pattern.len() == 7)
-        .unwrap()
-        .clone();
+        .map(|wire| wiring.get(wire).copied())
+        .collect()
+}
 
-    // 7 - 1 = top middle segment
-    let top_mid = *pattern7.difference(&pattern1).next().unwrap();
+// The segments which each wire could still be connected to
+type Domains = HashMap<char, HashSet<char>>;
 
-    // 0, 6, 9
-    let patterns_with_6_segments = patterns
-        .iter()
-        .filter(|pattern| pattern.len() == 6)
-        .cloned()
-        .collect::<Vec<_>>();
-    assert_eq!(patterns_with_6_segments.len(), 3);
-    // 0 and 9 contain 1, but 6 doesn't
-    let pattern6 = patterns_with_6_segments
-        .iter()
-        .find(|pattern| !pattern.is_superset(&pattern1))
-        .unwrap()
-        .clone();
-    // 8 - 6 = top right segment
-    let top_right = *pattern8.difference(&pattern6).next().unwrap();
-    // 1 - top right = bottom right segment
-    let bottom_right = *pattern1.iter().find(|&&c| c != top_right).unwrap();
-
-    // 9 contains all of 4, but 0 doesn't contain all of 4
-    let pattern9 = patterns_with_6_segments
-        .iter()
-        .find(|pattern| pattern.is_superset(&pattern4))
-        .unwrap()
-        .clone();
-    let bottom_left = *pattern8.difference(&pattern9).next().unwrap();
-    let pattern0 = patterns_with_6_segments
-        .iter()
-        .find(|pattern| ![&pattern6, &pattern9].contains(pattern))
-        .unwrap()
-        .clone();
-    // 8 - 0 = middle segment
-    let middle = *pattern8.difference(&pattern0).next().unwrap();
-
-    // 4 - (middle, top right, bottom right) = top left segment
-    let top_left = *pattern4
+/// Searches for (at most two) wirings, so we can tell whether the solution is unique.
+fn search(
+    table: &DisplayTable,
+    patterns: &[SegmentSet],
+    mut domains: Domains,
+    solutions: &mut Vec<Wiring>,
+) {
+    if solutions.len() >= 2 || !propagate(table, patterns, &mut domains) {
+        return;
+    }
+    let undecided = domains
         .iter()
-        .find(|c| ![middle, top_right, bottom_right].contains(c))
-        .unwrap();
+        .filter(|(_, domain)| domain.len() > 1)
+        .min_by_key(|(&wire, domain)| (domain.len(), wire));
+    match undecided {
+        None => {
+            let wiring = domains
+                .iter()
+                .map(|(&wire, domain)| (wire, *domain.iter().next().unwrap()))
+                .collect::<Wiring>();
+            let mut digits = HashSet::new();
+            let is_valid = patterns.iter().all(|pattern| {
+                match rewire(pattern, &wiring).and_then(|display| table.find_digit(&display)) {
+                    Some(digit) => digits.insert(digit),
+                    None => false,
+                }
+            });
+            if is_valid {
+                solutions.push(wiring);
+            }
+        }
+        Some((&wire, domain)) => {
+            let mut segments = domain.iter().copied().collect::<Vec<_>>();
+            segments.sort_unstable();
+            for segment in segments {
+                let mut domains = domains.clone();
+                domains.insert(wire, HashSet::from([segment]));
+                search(table, patterns, domains, solutions);
+            }
+        }
+    }
+}
 
-    // remaining = bottom middle segment
-    let bottom_mid = *pattern0
-        .iter()
-        .find(|c| ![top_left, top_mid, top_right, bottom_left, bottom_right].contains(c))
-        .unwrap();
-
-    let pattern2 = SegmentDisplay::from([top_mid, top_right, middle, bottom_left, bottom_mid]);
-    let pattern3 = SegmentDisplay::from([top_mid, top_right, middle, bottom_mid, bottom_right]);
-    let pattern5 = SegmentDisplay::from([top_left, top_mid, middle, bottom_mid, bottom_right]);
-    let lookup = [
-        pattern0, pattern1, pattern2, pattern3, pattern4, pattern5, pattern6, pattern7, pattern8,
-        pattern9,
-    ];
-    let mut value = 0;
-    for output_display in output {
-        value *= 10;
-        value += lookup.iter().position(|p| p == &output_display).unwrap() as i32;
+/// Narrows down the domains until nothing changes anymore.
+/// Returns false if some wire can no longer be connected to any segment.
+fn propagate(table: &DisplayTable, patterns: &[SegmentSet], domains: &mut Domains) -> bool {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for pattern in patterns {
+            // Wires inside the pattern must be connected to the segments of a candidate digit,
+            // and wires outside the pattern must be connected to the other segments.
+            let mut inside = HashSet::<char>::new();
+            let mut outside = HashSet::<char>::new();
+            for digit in &table.digits {
+                if digit.len() != pattern.len() {
+                    continue;
+                }
+                let is_candidate = domains.iter().all(|(wire, domain)| {
+                    if pattern.contains(wire) {
+                        domain.iter().any(|segment| digit.contains(segment))
+                    } else {
+                        domain.iter().any(|segment| !digit.contains(segment))
+                    }
+                });
+                if is_candidate {
+                    inside.extend(digit.iter().copied());
+                    outside.extend(table.segments.iter().filter(|s| !digit.contains(s)));
+                }
+            }
+            for (wire, domain) in domains.iter_mut() {
+                let allowed = if pattern.contains(wire) {
+                    &inside
+                } else {
+                    &outside
+                };
+                let old_len = domain.len();
+                domain.retain(|segment| allowed.contains(segment));
+                changed |= domain.len() != old_len;
+            }
+        }
+        // Wires which are decided can't be connected to the same segment as any other wire
+        let decided = domains
+            .iter()
+            .filter(|(_, domain)| domain.len() == 1)
+            .map(|(&wire, domain)| (wire, *domain.iter().next().unwrap()))
+            .collect::<Vec<_>>();
+        for (wire, segment) in decided {
+            for (&other_wire, domain) in domains.iter_mut() {
+                if other_wire != wire && domain.remove(&segment) {
+                    changed = true;
+                }
+            }
+        }
+        if domains.values().any(|domain| domain.is_empty()) {
+            return false;
+        }
     }
-    value
+    true
 }
 
 #[cfg(test)]
@@ -186,5 +485,114 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
     fn test_part2() {
         let input = input_generator(&LARGE_INPUT);
         assert_eq!(part2(&input), 61229);
+        assert_eq!(part2_search(&input), 61229);
+    }
+
+    #[test]
+    fn test_solve_wiring() {
+        let input = input_generator(&SMALL_INPUT);
+        let patterns = input[0].patterns.map(SegmentDisplay::to_set);
+        let wiring = solve_wiring(&SEVEN_SEGMENT, &patterns).unwrap();
+        let expected = "deafgbc".chars().zip("abcdefg".chars()).collect::<Wiring>();
+        assert_eq!(wiring, expected);
+    }
+
+    #[test]
+    fn test_decode_hexadecimal() {
+        let table = DisplayTable::new(&[
+            "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg",
+            "abcdfg", "abcdef", "bdefg", "abeg", "cdefg", "abdeg", "abde",
+        ]);
+        let scramble = "abcdefg".chars().zip("gfaebdc".chars()).collect::<Wiring>();
+        let patterns = table
+            .digits
+            .iter()
+            .rev()
+            .map(|digit| rewire(digit, &scramble).unwrap())
+            .collect::<Vec<_>>();
+        let output = [15, 0, 0, 13]
+            .iter()
+            .map(|&digit| rewire(&table.digits[digit], &scramble).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decode_with(&table, &patterns, &output), Ok(0xF00D));
+        let wiring = solve_wiring(&table, &patterns).unwrap();
+        assert!(scramble
+            .iter()
+            .all(|(segment, wire)| wiring[wire] == *segment));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let input = input_generator(&SMALL_INPUT);
+        let patterns = input[0].patterns.map(SegmentDisplay::to_set).to_vec();
+        let output = input[0].output.map(SegmentDisplay::to_set).to_vec();
+        // Only knowing the patterns for 1 and 7 isn't enough
+        let mut few_patterns = patterns.clone();
+        few_patterns.retain(|pattern| matches!(pattern.len(), 2 | 3));
+        assert_eq!(
+            decode_with(&SEVEN_SEGMENT, &few_patterns, &output),
+            Err(DecodeError::Ambiguous)
+        );
+        // Two patterns can't light up the same digit
+        let mut duplicate_patterns = patterns.clone();
+        duplicate_patterns.push(patterns[0].clone());
+        assert_eq!(
+            decode_with(&SEVEN_SEGMENT, &duplicate_patterns, &output),
+            Err(DecodeError::Inconsistent)
+        );
+        let mut bad_output = output.clone();
+        bad_output[0] = "abc".chars().collect();
+        assert_eq!(
+            decode_with(&SEVEN_SEGMENT, &patterns, &bad_output),
+            Err(DecodeError::UnknownDigit)
+        );
+        // Wires which aren't connected to any segment
+        bad_output[0] = "abh".chars().collect();
+        assert_eq!(
+            decode_with(&SEVEN_SEGMENT, &patterns, &bad_output),
+            Err(DecodeError::UnknownDigit)
+        );
+        let mut bad_patterns = patterns;
+        bad_patterns[0] = "abh".chars().collect();
+        assert_eq!(
+            decode_with(&SEVEN_SEGMENT, &bad_patterns, &output),
+            Err(DecodeError::Inconsistent)
+        );
+    }
+
+    #[test]
+    fn test_segment_display() {
+        let display = "cagedb".parse::<SegmentDisplay>().unwrap();
+        assert_eq!(display.to_string(), "abcdeg");
+        assert_eq!(display.len(), 6);
+        assert!(display.contains('e') && !display.contains('f'));
+        let one = "ab".parse::<SegmentDisplay>().unwrap();
+        assert!(display.is_superset(one));
+        assert_eq!((display - one).to_string(), "cdeg");
+        assert_eq!((!display).to_string(), "f");
+        assert_eq!((one | !display).to_string(), "abf");
+        assert_eq!((display & !one).to_string(), "cdeg");
+        assert_eq!("abz".parse::<SegmentDisplay>(), Err(()));
+        assert_eq!("abcdefgh".parse::<SegmentDisplay>(), Err(()));
+    }
+
+    #[test]
+    fn test_decode_fast() {
+        let input = input_generator(&LARGE_INPUT);
+        assert_eq!(
+            input.iter().map(decode_fast).collect::<Vec<_>>(),
+            input
+                .iter()
+                .map(|entry| Some(decode(entry) as u32))
+                .collect::<Vec<_>>()
+        );
+        let mut entry = input[0];
+        entry.output[0] = "abc".parse().unwrap();
+        assert_eq!(decode_fast(&entry), None);
+        // Scores as a 1, but the pattern for 1 is "ab"
+        let mut entry = input_generator(&SMALL_INPUT)[0];
+        assert_eq!(decode_fast(&entry), Some(5353));
+        entry.output[0] = "bd".parse().unwrap();
+        assert_eq!(decode_fast(&entry), None);
     }
 }
@@ -1,6 +1,8 @@
-use std::collections::{HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
 
-use crate::util::Vector2D;
+use crate::util::{write_ppm, Vector2D};
 
 type HeightMap = [Vec<u8>];
 
@@ -16,34 +18,95 @@ pub fn input_generator(input: &str) -> Vec<Vec<u8>> {
         .collect()
 }
 
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Connectivity {
+    /// Only horizontal and vertical neighbours
+    Four,
+    /// Also diagonal neighbours
+    Eight,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HeightMapRules {
+    /// Locations of at least this height are walls, which don't belong to any basin
+    pub wall_height: u8,
+    /// Which locations are neighbours of each other
+    pub connectivity: Connectivity,
+    /// Whether a flat region which is lower than all of its surroundings
+    /// consists of low points, rather than having no low points at all
+    pub plateaus: bool,
+}
+
+pub const PUZZLE_RULES: HeightMapRules = HeightMapRules {
+    wall_height: 9,
+    connectivity: Connectivity::Four,
+    plateaus: false,
+};
+
 #[aoc(day9, part1)]
 pub fn part1(map: &HeightMap) -> i32 {
-    let low_points = get_low_points(map);
+    let low_points = get_low_points(map, &PUZZLE_RULES);
     low_points
         .iter()
         .map(|pos| map[pos.y() as usize][pos.x() as usize] as i32 + 1)
         .sum()
 }
 
-fn get_low_points(map: &HeightMap) -> Vec<Vector2D> {
+fn get_low_points(map: &HeightMap, rules: &HeightMapRules) -> Vec<Vector2D> {
     let mut low_points: Vec<Vector2D> = vec![];
+    let mut visited = map
+        .iter()
+        .map(|row| vec![false; row.len()])
+        .collect::<Vec<_>>();
     for (y, row) in map.iter().enumerate() {
-        for (x, height) in row.iter().enumerate() {
+        for (x, &height) in row.iter().enumerate() {
+            if height >= rules.wall_height || visited[y][x] {
+                continue;
+            }
             let pos = Vector2D::new(x as i32, y as i32);
-            let neighbours = get_neighbour_heights(map, pos);
-            if neighbours
-                .iter()
-                .all(|neighbour_height| height < neighbour_height)
-            {
-                low_points.push(pos);
+            if !rules.plateaus {
+                let neighbours = get_neighbour_heights(map, pos, rules.connectivity);
+                if neighbours
+                    .iter()
+                    .all(|&neighbour_height| height < neighbour_height)
+                {
+                    low_points.push(pos);
+                }
+                continue;
+            }
+            // Find the flat region around this location, and check whether it's surrounded by higher ground
+            let mut region = vec![pos];
+            let mut is_low = true;
+            visited[y][x] = true;
+            let mut i = 0;
+            while i < region.len() {
+                for neighbour_pos in get_neighbours(map, region[i], rules.connectivity) {
+                    let (nx, ny) = (neighbour_pos.x() as usize, neighbour_pos.y() as usize);
+                    let neighbour_height = map[ny][nx];
+                    if neighbour_height < height {
+                        is_low = false;
+                    } else if neighbour_height == height && !visited[ny][nx] {
+                        visited[ny][nx] = true;
+                        region.push(neighbour_pos);
+                    }
+                }
+                i += 1;
+            }
+            if is_low {
+                low_points.extend(region);
             }
         }
     }
     low_points
 }
 
-fn get_neighbours(map: &HeightMap, pos: Vector2D) -> Vec<Vector2D> {
-    pos.neighbours()
+fn get_neighbours(map: &HeightMap, pos: Vector2D, connectivity: Connectivity) -> Vec<Vector2D> {
+    let neighbours: Box<dyn Iterator<Item = Vector2D>> = match connectivity {
+        Connectivity::Four => Box::new(pos.neighbours()),
+        Connectivity::Eight => Box::new(pos.neighbours_diagonal()),
+    };
+    neighbours
         .filter(|pos| {
             pos.y() >= 0
                 && pos.y() < map.len() as i32
@@ -53,8 +116,8 @@ fn get_neighbours(map: &HeightMap, pos: Vector2D) -> Vec<Vector2D> {
         .collect()
 }
 
-fn get_neighbour_heights(map: &HeightMap, pos: Vector2D) -> Vec<u8> {
-    get_neighbours(map, pos)
+fn get_neighbour_heights(map: &HeightMap, pos: Vector2D, connectivity: Connectivity) -> Vec<u8> {
+    get_neighbours(map, pos, connectivity)
         .into_iter()
         .map(|pos| map[pos.y() as usize][pos.x() as usize])
         .collect()
@@ -62,32 +125,186 @@ fn get_neighbour_heights(map: &HeightMap, pos: Vector2D) -> Vec<u8> {
 
 #[aoc(day9, part2)]
 pub fn part2(map: &HeightMap) -> i32 {
-    let mut basin_sizes = get_low_points(map)
+    let mut basin_sizes = segment_basins(map, &PUZZLE_RULES)
+        .basins
         .into_iter()
-        .map(|low_point| get_basin_size(map, low_point))
+        .map(|basin| basin.area as i32)
         .collect::<Vec<_>>();
     basin_sizes.sort_unstable();
     basin_sizes.reverse();
     basin_sizes.into_iter().take(3).product()
 }
 
-fn get_basin_size(map: &HeightMap, low_point: Vector2D) -> i32 {
-    let mut visited = HashSet::<Vector2D>::new();
-    let mut queue = VecDeque::<Vector2D>::new();
-    queue.push_back(low_point);
-    while let Some(pos) = queue.pop_front() {
-        visited.insert(pos);
-        for neighbour_pos in get_neighbours(map, pos) {
-            if visited.contains(&neighbour_pos) || queue.contains(&neighbour_pos) {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Label {
+    /// Walls don't belong to any basin
+    Ridge,
+    /// Index of the basin containing this location
+    Basin(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Basin {
+    /// Number of locations in the basin
+    pub area: usize,
+    /// Number of edges between a location in the basin and a location outside it (or the map's border)
+    pub perimeter: usize,
+    pub top_left: Vector2D,
+    pub bottom_right: Vector2D,
+}
+
+#[derive(Debug, Clone)]
+pub struct Segmentation {
+    pub labels: Vec<Vec<Label>>,
+    pub basins: Vec<Basin>,
+}
+
+/// Labels every location with the basin of the low point it flows into.
+/// Basins are numbered in the order of their low points, row by row.
+/// Locations which can't reach any low point get a basin for each flat region they lead down to,
+/// numbered after all basins of the low points.
+///
+/// The basins are flooded from their low points in order of height, so where two basins meet,
+/// every location goes to the basin which reaches it at the lowest level.
+pub fn segment_basins(map: &HeightMap, rules: &HeightMapRules) -> Segmentation {
+    let mut labels = map
+        .iter()
+        .map(|row| vec![Label::Ridge; row.len()])
+        .collect::<Vec<_>>();
+    // Queue of (height, insertion order, x, y, basin), lowest height first
+    let mut queue = BinaryHeap::<Reverse<(u8, usize, usize, usize, usize)>>::new();
+    let mut order = 0;
+    let mut nb_basins = 0;
+    // Locations which can't reach any low point only lead down to flat regions
+    // which don't count as low points, so those regions get a basin of their own
+    let flat_regions = HeightMapRules {
+        plateaus: true,
+        ..*rules
+    };
+    for seeds in [
+        get_low_points(map, rules),
+        get_low_points(map, &flat_regions),
+    ] {
+        for pos in seeds {
+            let (x, y) = (pos.x() as usize, pos.y() as usize);
+            if labels[y][x] != Label::Ridge {
                 continue;
             }
-            let height = map[neighbour_pos.y() as usize][neighbour_pos.x() as usize];
-            if height < 9 {
-                queue.push_back(neighbour_pos);
+            // The low points of a plateau all belong to the same basin
+            let id = get_neighbours(map, pos, rules.connectivity)
+                .into_iter()
+                .find_map(|neighbour_pos| {
+                    let (nx, ny) = (neighbour_pos.x() as usize, neighbour_pos.y() as usize);
+                    match labels[ny][nx] {
+                        Label::Basin(id) if map[ny][nx] == map[y][x] => Some(id),
+                        _ => None,
+                    }
+                })
+                .unwrap_or_else(|| {
+                    nb_basins += 1;
+                    nb_basins - 1
+                });
+            labels[y][x] = Label::Basin(id);
+            queue.push(Reverse((map[y][x], order, x, y, id)));
+            order += 1;
+        }
+        while let Some(Reverse((_, _, x, y, id))) = queue.pop() {
+            let pos = Vector2D::new(x as i32, y as i32);
+            for neighbour_pos in get_neighbours(map, pos, rules.connectivity) {
+                let (nx, ny) = (neighbour_pos.x() as usize, neighbour_pos.y() as usize);
+                if map[ny][nx] < rules.wall_height && labels[ny][nx] == Label::Ridge {
+                    labels[ny][nx] = Label::Basin(id);
+                    queue.push(Reverse((map[ny][nx], order, nx, ny, id)));
+                    order += 1;
+                }
             }
         }
     }
-    visited.len() as i32
+    let mut basins = vec![None::<Basin>; nb_basins];
+    for (y, row) in labels.iter().enumerate() {
+        for (x, &label) in row.iter().enumerate() {
+            let id = match label {
+                Label::Basin(id) => id,
+                Label::Ridge => continue,
+            };
+            let pos = Vector2D::new(x as i32, y as i32);
+            let basin = basins[id].get_or_insert(Basin {
+                area: 0,
+                perimeter: 0,
+                top_left: pos,
+                bottom_right: pos,
+            });
+            basin.area += 1;
+            basin.top_left = basin.top_left.zip_with(&pos, |a, b| *a.min(b));
+            basin.bottom_right = basin.bottom_right.zip_with(&pos, |a, b| *a.max(b));
+            // Sides on the border of the map also count towards the perimeter
+            let sides = get_neighbours(map, pos, Connectivity::Four);
+            basin.perimeter += 4 - sides.len();
+            basin.perimeter += sides
+                .iter()
+                .filter(|side| labels[side.y() as usize][side.x() as usize] != label)
+                .count();
+        }
+    }
+    let basins = basins.into_iter().map(Option::unwrap).collect();
+    Segmentation { labels, basins }
+}
+
+const BASIN_COLORS: [[u8; 3]; 8] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+];
+
+fn label_color(label: Label) -> [u8; 3] {
+    match label {
+        Label::Ridge => [0, 0, 0],
+        Label::Basin(id) => BASIN_COLORS[id % BASIN_COLORS.len()],
+    }
+}
+
+/// Prints the height map with every basin in a different background color,
+/// using ANSI escape codes.
+#[allow(dead_code)]
+pub fn render_ansi(map: &HeightMap, segmentation: &Segmentation) -> String {
+    let mut result = String::new();
+    for (row, labels) in map.iter().zip(segmentation.labels.iter()) {
+        for (height, &label) in row.iter().zip(labels.iter()) {
+            let [r, g, b] = label_color(label);
+            result += &format!("\x1b[48;2;{};{};{}m{}", r, g, b, height);
+        }
+        result += "\x1b[0m\n";
+    }
+    result
+}
+
+/// Draws every basin in a different color as a PPM image,
+/// with each location drawn as a square of `scale` by `scale` pixels.
+#[allow(dead_code)]
+pub fn write_basin_image(
+    out: &mut impl Write,
+    segmentation: &Segmentation,
+    scale: usize,
+) -> io::Result<()> {
+    let height = segmentation.labels.len();
+    let width = segmentation.labels.first().map_or(0, |row| row.len());
+    let pixels = segmentation
+        .labels
+        .iter()
+        .flat_map(|row| {
+            let pixel_row = row
+                .iter()
+                .flat_map(|&label| [label_color(label)].repeat(scale))
+                .collect::<Vec<_>>();
+            pixel_row.repeat(scale)
+        })
+        .collect::<Vec<_>>();
+    write_ppm(out, width * scale, height * scale, &pixels)
 }
 
 #[cfg(test)]
@@ -116,4 +333,166 @@ mod tests {
         let input = input_generator(&TEST_INPUT);
         assert_eq!(part2(&input), 1134);
     }
+
+    #[test]
+    fn test_segment_basins() {
+        let input = input_generator(&TEST_INPUT);
+        let segmentation = segment_basins(&input, &PUZZLE_RULES);
+        let areas = segmentation
+            .basins
+            .iter()
+            .map(|basin| basin.area)
+            .collect::<Vec<_>>();
+        assert_eq!(areas, vec![3, 9, 14, 9]);
+        assert_eq!(
+            segmentation.basins[0],
+            Basin {
+                area: 3,
+                perimeter: 8,
+                top_left: Vector2D::new(0, 0),
+                bottom_right: Vector2D::new(1, 1)
+            }
+        );
+        assert_eq!(
+            segmentation.basins[1],
+            Basin {
+                area: 9,
+                perimeter: 18,
+                top_left: Vector2D::new(5, 0),
+                bottom_right: Vector2D::new(9, 2)
+            }
+        );
+        assert_eq!(segmentation.labels[0][2], Label::Ridge);
+        assert_eq!(segmentation.labels[4][9], Label::Basin(3));
+    }
+
+    #[test]
+    fn test_rules() {
+        let input = input_generator(&TEST_INPUT);
+        let rules = HeightMapRules {
+            wall_height: 8,
+            ..PUZZLE_RULES
+        };
+        let segmentation = segment_basins(&input, &rules);
+        assert_eq!(segmentation.labels[1][2], Label::Ridge);
+        assert_eq!(segmentation.labels[1][3], Label::Basin(2));
+        assert_eq!(segmentation.basins[2].area, 7);
+
+        let input = input_generator("1919\n9191\n1919");
+        let four = segment_basins(&input, &PUZZLE_RULES);
+        assert_eq!(four.basins.len(), 6);
+        let rules = HeightMapRules {
+            connectivity: Connectivity::Eight,
+            ..PUZZLE_RULES
+        };
+        let eight = segment_basins(&input, &rules);
+        assert_eq!(eight.basins.len(), 1);
+        assert_eq!(eight.basins[0].area, 6);
+        assert_eq!(eight.basins[0].perimeter, 24);
+    }
+
+    #[test]
+    fn test_watershed() {
+        let input = input_generator("15351");
+        assert_eq!(get_low_points(&input, &PUZZLE_RULES).len(), 3);
+        assert_eq!(segment_basins(&input, &PUZZLE_RULES).basins.len(), 3);
+        // Several low points without any walls in between
+        let input = input_generator("1253521\n2354532");
+        let segmentation = segment_basins(&input, &PUZZLE_RULES);
+        assert_eq!(segmentation.basins.len(), 3);
+        assert_eq!(
+            segmentation.labels[1][..2],
+            [Label::Basin(0), Label::Basin(0)]
+        );
+        assert_eq!(segmentation.labels[1][3], Label::Basin(1));
+        assert_eq!(
+            segmentation.labels[1][5..],
+            [Label::Basin(2), Label::Basin(2)]
+        );
+        // The column of 5s flows into its lowest neighbours, which belong to the first basin
+        assert_eq!(
+            segmentation.basins[0],
+            Basin {
+                area: 6,
+                perimeter: 10,
+                top_left: Vector2D::new(0, 0),
+                bottom_right: Vector2D::new(2, 1)
+            }
+        );
+        assert_eq!(
+            segmentation
+                .basins
+                .iter()
+                .map(|basin| basin.area)
+                .sum::<usize>(),
+            14
+        );
+    }
+
+    #[test]
+    fn test_flat_regions() {
+        // Without any low points, every flat region at the bottom gets its own basin
+        let input = input_generator("9999\n9119\n9999");
+        assert_eq!(get_low_points(&input, &PUZZLE_RULES), vec![]);
+        let segmentation = segment_basins(&input, &PUZZLE_RULES);
+        assert_eq!(
+            segmentation.basins,
+            vec![Basin {
+                area: 2,
+                perimeter: 6,
+                top_left: Vector2D::new(1, 1),
+                bottom_right: Vector2D::new(2, 1)
+            }]
+        );
+        assert_eq!(segmentation.labels[1][1], Label::Basin(0));
+        let input = input_generator("113211\n999929\n099999");
+        let segmentation = segment_basins(&input, &PUZZLE_RULES);
+        assert_eq!(segmentation.basins.len(), 3);
+        // The low point comes first, then the flat regions row by row
+        assert_eq!(segmentation.labels[2][0], Label::Basin(0));
+        assert_eq!(
+            segmentation.labels[0][..2],
+            [Label::Basin(1), Label::Basin(1)]
+        );
+        assert_eq!(segmentation.labels[1][4], Label::Basin(2));
+    }
+
+    #[test]
+    fn test_plateaus() {
+        let input = input_generator("99999\n93349\n99999");
+        assert_eq!(get_low_points(&input, &PUZZLE_RULES), vec![]);
+        let rules = HeightMapRules {
+            plateaus: true,
+            ..PUZZLE_RULES
+        };
+        assert_eq!(
+            get_low_points(&input, &rules),
+            vec![Vector2D::new(1, 1), Vector2D::new(2, 1)]
+        );
+        // Same results as before for maps without plateaus
+        let input = input_generator(&TEST_INPUT);
+        assert_eq!(
+            get_low_points(&input, &rules),
+            get_low_points(&input, &PUZZLE_RULES)
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let input = input_generator(&TEST_INPUT);
+        let segmentation = segment_basins(&input, &PUZZLE_RULES);
+        let ansi = render_ansi(&input, &segmentation);
+        assert!(ansi.starts_with("\x1b[48;2;230;25;75m2\x1b[48;2;230;25;75m1\x1b[48;2;0;0;0m9"));
+        let escape = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        assert_eq!(escape.replace_all(&ansi, ""), format!("{}\n", *TEST_INPUT));
+
+        let mut out = Vec::new();
+        write_basin_image(&mut out, &segmentation, 2).unwrap();
+        let header = b"P6\n20 10\n255\n";
+        assert_eq!(out.len(), header.len() + 20 * 10 * 3);
+        assert_eq!(
+            &out[header.len()..header.len() + 6],
+            &[230, 25, 75, 230, 25, 75]
+        );
+    }
 }
@@ -8,7 +8,7 @@ extern crate aoc_runner_derive;
 extern crate lazy_static;
 
 mod day1;
-mod day10;
+pub mod day10;
 mod day11;
 mod day12;
 mod day13;
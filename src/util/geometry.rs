@@ -0,0 +1,401 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{gcd_64, Vector2D};
+
+type Point = (i128, i128);
+
+#[inline]
+fn to_point(v: Vector2D) -> Point {
+    (v.x() as i128, v.y() as i128)
+}
+
+#[inline]
+fn from_point(p: Point) -> Vector2D {
+    Vector2D::new(p.0 as i32, p.1 as i32)
+}
+
+#[inline]
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+#[inline]
+fn dot(a: Point, b: Point) -> i128 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+#[inline]
+fn cross(a: Point, b: Point) -> i128 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// Iterates over the grid points on the line from `start` to `end` (both inclusive),
+/// using Bresenham's line algorithm.
+pub fn bresenham(start: Vector2D, end: Vector2D) -> impl Iterator<Item = Vector2D> {
+    // https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm#All_cases
+    let dx = (end.x() - start.x()).abs();
+    let dy = -(end.y() - start.y()).abs();
+    let sx = (end.x() - start.x()).signum();
+    let sy = (end.y() - start.y()).signum();
+    let mut err = dx + dy;
+    let mut pos = Some(start);
+    std::iter::from_fn(move || {
+        let current = pos?;
+        pos = if current == end {
+            None
+        } else {
+            let mut next = current;
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                *next.x_mut() += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                *next.y_mut() += sy;
+            }
+            Some(next)
+        };
+        Some(current)
+    })
+}
+
+/// A line segment between two grid points, covering every grid point
+/// which lies exactly on the line between them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Segment {
+    pub start: Vector2D,
+    pub end: Vector2D,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Intersection {
+    None,
+    Point(Vector2D),
+    Overlap(Segment),
+}
+
+#[allow(dead_code)]
+impl Segment {
+    pub fn new(start: Vector2D, end: Vector2D) -> Self {
+        Self { start, end }
+    }
+
+    pub fn is_axis_aligned(&self) -> bool {
+        self.start.x() == self.end.x() || self.start.y() == self.end.y()
+    }
+
+    /// The smallest grid step along the segment, normalized to point
+    /// towards positive x (or positive y for vertical segments).
+    /// Returns `None` if the segment is a single point.
+    fn direction(&self) -> Option<Point> {
+        let (dx, dy) = sub(to_point(self.end), to_point(self.start));
+        let gcd = gcd_64(dx as i64, dy as i64) as i128;
+        if gcd == 0 {
+            return None;
+        }
+        let (dx, dy) = (dx / gcd, dy / gcd);
+        if dx < 0 || (dx == 0 && dy < 0) {
+            Some((-dx, -dy))
+        } else {
+            Some((dx, dy))
+        }
+    }
+
+    /// The number of grid points on the segment.
+    pub fn len(&self) -> u64 {
+        let (dx, dy) = sub(to_point(self.end), to_point(self.start));
+        gcd_64(dx as i64, dy as i64) as u64 + 1
+    }
+
+    /// Iterates over all grid points on the segment.
+    pub fn points(&self) -> impl Iterator<Item = Vector2D> {
+        let start = self.start;
+        let (step, nb_steps) = match self.direction() {
+            Some(dir) => {
+                let (dx, dy) = sub(to_point(self.end), to_point(self.start));
+                let sign = if dot((dx, dy), dir) < 0 { -1 } else { 1 };
+                (from_point((dir.0 * sign, dir.1 * sign)), self.len() - 1)
+            }
+            None => (Vector2D::zero(), 0),
+        };
+        (0..=nb_steps as i32).map(move |i| start + step * i)
+    }
+
+    pub fn contains(&self, point: Vector2D) -> bool {
+        let a = to_point(self.start);
+        let r = sub(to_point(self.end), a);
+        if r == (0, 0) {
+            // Every point is collinear with a single point
+            return point == self.start;
+        }
+        let ap = sub(to_point(point), a);
+        cross(r, ap) == 0 && (0..=dot(r, r)).contains(&dot(ap, r))
+    }
+
+    /// Computes the grid points shared by both segments.
+    pub fn intersection(&self, other: &Segment) -> Intersection {
+        let (a, c) = (to_point(self.start), to_point(other.start));
+        let (r, s) = (sub(to_point(self.end), a), sub(to_point(other.end), c));
+        if r == (0, 0) || s == (0, 0) {
+            let (point, segment) = if r == (0, 0) {
+                (self.start, other)
+            } else {
+                (other.start, self)
+            };
+            return if segment.contains(point) {
+                Intersection::Point(point)
+            } else {
+                Intersection::None
+            };
+        }
+        let ca = sub(c, a);
+        let denom = cross(r, s);
+        if denom == 0 {
+            // Parallel
+            if cross(ca, r) != 0 {
+                return Intersection::None;
+            }
+            // Collinear, so compare the projections onto their common line
+            let dir = self.direction().unwrap();
+            let offset = cross(dir, a);
+            let (lo1, hi1) = min_max(dot(a, dir), dot(to_point(self.end), dir));
+            let (lo2, hi2) = min_max(dot(c, dir), dot(to_point(other.end), dir));
+            let (lo, hi) = (lo1.max(lo2), hi1.min(hi2));
+            return match lo.cmp(&hi) {
+                std::cmp::Ordering::Greater => Intersection::None,
+                std::cmp::Ordering::Equal => Intersection::Point(line_point(dir, offset, lo)),
+                std::cmp::Ordering::Less => Intersection::Overlap(Segment::new(
+                    line_point(dir, offset, lo),
+                    line_point(dir, offset, hi),
+                )),
+            };
+        }
+        // Solve a + t * r = c + u * s, with t = t_num / denom and u = u_num / denom
+        let (t_num, u_num, denom) = if denom < 0 {
+            (-cross(ca, s), -cross(ca, r), -denom)
+        } else {
+            (cross(ca, s), cross(ca, r), denom)
+        };
+        if !(0..=denom).contains(&t_num) || !(0..=denom).contains(&u_num) {
+            return Intersection::None;
+        }
+        let x = a.0 * denom + r.0 * t_num;
+        let y = a.1 * denom + r.1 * t_num;
+        if x % denom != 0 || y % denom != 0 {
+            // Crosses in between grid points
+            return Intersection::None;
+        }
+        Intersection::Point(from_point((x / denom, y / denom)))
+    }
+}
+
+fn min_max(a: i128, b: i128) -> (i128, i128) {
+    (a.min(b), a.max(b))
+}
+
+/// Finds the point on the line with the given direction and offset (cross product with the direction)
+/// whose projection onto the direction equals `projection`.
+fn line_point(dir: Point, offset: i128, projection: i128) -> Vector2D {
+    let norm = dot(dir, dir);
+    let perp = (-dir.1, dir.0);
+    from_point((
+        (projection * dir.0 + offset * perp.0) / norm,
+        (projection * dir.1 + offset * perp.1) / norm,
+    ))
+}
+
+/// Counts the grid points covered by at least two segments.
+///
+/// Only the pairwise intersections are computed, so this runs in time quadratic
+/// in the number of segments regardless of how long those segments are.
+#[allow(dead_code)]
+pub fn count_overlapping_points(segments: &[Segment]) -> u64 {
+    let mut points = HashSet::<Vector2D>::new();
+    let mut lines = HashMap::<(Point, i128), Vec<(i128, i128)>>::new();
+    for (i, first) in segments.iter().enumerate() {
+        for second in &segments[i + 1..] {
+            match first.intersection(second) {
+                Intersection::None => {}
+                Intersection::Point(point) => {
+                    points.insert(point);
+                }
+                Intersection::Overlap(overlap) => {
+                    let dir = overlap.direction().unwrap();
+                    let offset = cross(dir, to_point(overlap.start));
+                    let interval = min_max(
+                        dot(to_point(overlap.start), dir),
+                        dot(to_point(overlap.end), dir),
+                    );
+                    lines.entry((dir, offset)).or_default().push(interval);
+                }
+            }
+        }
+    }
+    // Merge the overlaps on each line into disjoint segments
+    let mut overlaps = Vec::<Segment>::new();
+    for ((dir, offset), mut intervals) in lines {
+        intervals.sort_unstable();
+        let mut merged = Vec::<(i128, i128)>::new();
+        for (lo, hi) in intervals {
+            match merged.last_mut() {
+                Some(last) if lo <= last.1 => last.1 = last.1.max(hi),
+                _ => merged.push((lo, hi)),
+            }
+        }
+        overlaps.extend(merged.into_iter().map(|(lo, hi)| {
+            Segment::new(line_point(dir, offset, lo), line_point(dir, offset, hi))
+        }));
+    }
+    let mut count = overlaps.iter().map(|overlap| overlap.len()).sum::<u64>();
+    // Overlaps on different lines may cross each other
+    for (i, first) in overlaps.iter().enumerate() {
+        for second in &overlaps[i + 1..] {
+            if let Intersection::Point(point) = first.intersection(second) {
+                points.insert(point);
+            }
+        }
+    }
+    // Count the remaining points, and correct for points counted in multiple overlaps
+    for point in points {
+        let nb_overlaps = overlaps
+            .iter()
+            .filter(|overlap| overlap.contains(point))
+            .count() as u64;
+        if nb_overlaps == 0 {
+            count += 1;
+        } else {
+            count -= nb_overlaps - 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(x1: i32, y1: i32, x2: i32, y2: i32) -> Segment {
+        Segment::new(Vector2D::new(x1, y1), Vector2D::new(x2, y2))
+    }
+
+    #[test]
+    fn test_bresenham() {
+        let points = bresenham(Vector2D::new(0, 0), Vector2D::new(4, 2)).collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![
+                Vector2D::new(0, 0),
+                Vector2D::new(1, 1),
+                Vector2D::new(2, 1),
+                Vector2D::new(3, 2),
+                Vector2D::new(4, 2)
+            ]
+        );
+        let points = bresenham(Vector2D::new(3, 3), Vector2D::new(1, 1)).collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![
+                Vector2D::new(3, 3),
+                Vector2D::new(2, 2),
+                Vector2D::new(1, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_points() {
+        let points = segment(0, 0, 6, -4).points().collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![
+                Vector2D::new(0, 0),
+                Vector2D::new(3, -2),
+                Vector2D::new(6, -4)
+            ]
+        );
+        assert_eq!(segment(6, -4, 0, 0).len(), 3);
+        assert_eq!(segment(1, 1, 1, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_intersection() {
+        assert_eq!(
+            segment(0, 0, 4, 4).intersection(&segment(0, 4, 4, 0)),
+            Intersection::Point(Vector2D::new(2, 2))
+        );
+        // Crossing in between grid points
+        assert_eq!(
+            segment(0, 0, 3, 3).intersection(&segment(0, 3, 3, 0)),
+            Intersection::None
+        );
+        assert_eq!(
+            segment(0, 0, 10, 5).intersection(&segment(4, 2, 20, 10)),
+            Intersection::Overlap(segment(4, 2, 10, 5))
+        );
+        assert_eq!(
+            segment(0, 0, 2, 0).intersection(&segment(5, 0, 2, 0)),
+            Intersection::Point(Vector2D::new(2, 0))
+        );
+        assert_eq!(
+            segment(0, 0, 2, 0).intersection(&segment(0, 1, 2, 1)),
+            Intersection::None
+        );
+    }
+
+    #[test]
+    fn test_count_overlapping_points() {
+        let segments = [
+            segment(0, 0, 1_000_000_000, 0),
+            segment(500_000_000, 0, 1_500_000_000, 0),
+            segment(750_000_000, -10, 750_000_000, 10),
+            segment(0, 5, 0, -5),
+        ];
+        assert_eq!(count_overlapping_points(&segments), 500_000_001 + 1);
+        let segments = [
+            segment(8, 8, 8, 8),
+            segment(8, 5, 8, 5),
+            segment(6, 7, 0, 3),
+        ];
+        assert_eq!(count_overlapping_points(&segments), 0);
+    }
+
+    #[test]
+    fn test_count_overlapping_points_random() {
+        // Simple linear congruential generator, so the test is reproducible
+        let mut state = 12345u64;
+        let mut random = |range: i32| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) % range as u64) as i32
+        };
+        for _ in 0..3000 {
+            let nb_segments = 2 + random(4);
+            let segments = (0..nb_segments)
+                .map(|_| {
+                    let start = Vector2D::new(random(10), random(10));
+                    // Include plenty of single points and axis-aligned segments
+                    match random(4) {
+                        0 => Segment::new(start, start),
+                        1 => Segment::new(start, Vector2D::new(random(10), start.y())),
+                        _ => Segment::new(start, Vector2D::new(random(10), random(10))),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let mut counts = HashMap::<Vector2D, u64>::new();
+            for segment in &segments {
+                for point in segment.points() {
+                    *counts.entry(point).or_default() += 1;
+                }
+            }
+            let expected = counts.values().filter(|&&count| count >= 2).count() as u64;
+            assert_eq!(
+                count_overlapping_points(&segments),
+                expected,
+                "{:?}",
+                segments
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{Result, Write};
+
+/// Writes a binary grayscale image in the PGM format, with one byte per pixel.
+#[allow(dead_code)]
+pub fn write_pgm(out: &mut impl Write, width: usize, height: usize, pixels: &[u8]) -> Result<()> {
+    assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+    write!(out, "P5\n{} {}\n255\n", width, height)?;
+    out.write_all(pixels)
+}
+
+/// Writes a binary color image in the PPM format, with one RGB triple per pixel.
+#[allow(dead_code)]
+pub fn write_ppm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[[u8; 3]],
+) -> Result<()> {
+    assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    for pixel in pixels {
+        out.write_all(pixel)?;
+    }
+    Ok(())
+}
+
+/// Maps a value between 0 and 1 to a black-red-yellow-white heat map color.
+#[allow(dead_code)]
+pub fn heat_color(value: f64) -> [u8; 3] {
+    let scaled = value.clamp(0.0, 1.0) * 3.0;
+    let channel = |offset: f64| ((scaled - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pgm() {
+        let mut out = Vec::new();
+        write_pgm(&mut out, 2, 1, &[0, 255]).unwrap();
+        assert_eq!(out, b"P5\n2 1\n255\n\x00\xff");
+    }
+
+    #[test]
+    fn test_heat_color() {
+        assert_eq!(heat_color(0.0), [0, 0, 0]);
+        assert_eq!(heat_color(0.5), [255, 128, 0]);
+        assert_eq!(heat_color(1.0), [255, 255, 255]);
+    }
+}
@@ -1,7 +1,13 @@
+pub use geometry::*;
+pub use image::*;
 pub use math::*;
+pub use ocr::*;
 pub use slice::*;
 pub use vector::*;
 
+mod geometry;
+mod image;
 mod math;
+mod ocr;
 mod slice;
 mod vector;
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+
+use super::Vector2D;
+
+/// The letters which appear in puzzle answers, drawn 6 dots tall and 4 dots wide (except for `Y`).
+const ALPHABET_6: [(char, &str); 18] = [
+    ('A', ".##.\n#..#\n#..#\n####\n#..#\n#..#"),
+    ('B', "###.\n#..#\n###.\n#..#\n#..#\n###."),
+    ('C', ".##.\n#..#\n#...\n#...\n#..#\n.##."),
+    ('E', "####\n#...\n###.\n#...\n#...\n####"),
+    ('F', "####\n#...\n###.\n#...\n#...\n#..."),
+    ('G', ".##.\n#..#\n#...\n#.##\n#..#\n.###"),
+    ('H', "#..#\n#..#\n####\n#..#\n#..#\n#..#"),
+    ('I', ".###\n..#.\n..#.\n..#.\n..#.\n.###"),
+    ('J', "..##\n...#\n...#\n...#\n#..#\n.##."),
+    ('K', "#..#\n#.#.\n##..\n#.#.\n#.#.\n#..#"),
+    ('L', "#...\n#...\n#...\n#...\n#...\n####"),
+    ('O', ".##.\n#..#\n#..#\n#..#\n#..#\n.##."),
+    ('P', "###.\n#..#\n#..#\n###.\n#...\n#..."),
+    ('R', "###.\n#..#\n#..#\n###.\n#.#.\n#..#"),
+    ('S', ".###\n#...\n#...\n.##.\n...#\n###."),
+    ('U', "#..#\n#..#\n#..#\n#..#\n#..#\n.##."),
+    ('Y', "#...#\n#...#\n.#.#.\n..#..\n..#..\n..#.."),
+    ('Z', "####\n...#\n..#.\n.#..\n#...\n####"),
+];
+
+/// The larger letters, drawn 6 dots wide and 10 dots tall.
+const ALPHABET_10: [(char, &str); 15] = [
+    (
+        'A',
+        "..##..\n.#..#.\n#....#\n#....#\n#....#\n######\n#....#\n#....#\n#....#\n#....#",
+    ),
+    (
+        'B',
+        "#####.\n#....#\n#....#\n#....#\n#####.\n#....#\n#....#\n#....#\n#....#\n#####.",
+    ),
+    (
+        'C',
+        ".####.\n#....#\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#....#\n.####.",
+    ),
+    (
+        'E',
+        "######\n#.....\n#.....\n#.....\n#####.\n#.....\n#.....\n#.....\n#.....\n######",
+    ),
+    (
+        'F',
+        "######\n#.....\n#.....\n#.....\n#####.\n#.....\n#.....\n#.....\n#.....\n#.....",
+    ),
+    (
+        'G',
+        ".####.\n#....#\n#.....\n#.....\n#.....\n#..###\n#....#\n#....#\n#...##\n.###.#",
+    ),
+    (
+        'H',
+        "#....#\n#....#\n#....#\n#....#\n######\n#....#\n#....#\n#....#\n#....#\n#....#",
+    ),
+    (
+        'J',
+        "...###\n....#.\n....#.\n....#.\n....#.\n....#.\n....#.\n#...#.\n#...#.\n.###..",
+    ),
+    (
+        'K',
+        "#....#\n#...#.\n#..#..\n#.#...\n##....\n##....\n#.#...\n#..#..\n#...#.\n#....#",
+    ),
+    (
+        'L',
+        "#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n#.....\n######",
+    ),
+    (
+        'N',
+        "#....#\n##...#\n##...#\n#.#..#\n#.#..#\n#..#.#\n#..#.#\n#...##\n#...##\n#....#",
+    ),
+    (
+        'P',
+        "#####.\n#....#\n#....#\n#....#\n#####.\n#.....\n#.....\n#.....\n#.....\n#.....",
+    ),
+    (
+        'R',
+        "#####.\n#....#\n#....#\n#....#\n#####.\n#..#..\n#...#.\n#...#.\n#....#\n#....#",
+    ),
+    (
+        'X',
+        "#....#\n#....#\n.#..#.\n.#..#.\n..##..\n..##..\n.#..#.\n.#..#.\n#....#\n#....#",
+    ),
+    (
+        'Z',
+        "######\n.....#\n.....#\n....#.\n...#..\n..#...\n.#....\n#.....\n#.....\n######",
+    ),
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum OcrError {
+    /// The dots are not 6 or 10 rows tall (or there are no dots at all)
+    UnsupportedHeight(i32),
+    /// The glyph at the given index doesn't match any letter, and is drawn like this
+    UnknownGlyph(usize, String),
+}
+
+/// Reads the letters drawn by a set of dots, such as the code on the folded paper of day 13.
+///
+/// Letters are separated by at least one empty column. Some letters (such as `I`)
+/// are narrower than their glyph, so every horizontal position within the glyph is tried.
+pub fn recognize(dots: &HashSet<Vector2D>) -> Result<String, OcrError> {
+    let min_y = dots.iter().map(|pos| pos.y()).min();
+    let max_y = dots.iter().map(|pos| pos.y()).max();
+    let height = match (min_y, max_y) {
+        (Some(min_y), Some(max_y)) => max_y - min_y + 1,
+        _ => 0,
+    };
+    let (alphabet, cell_width): (&[(char, &str)], i32) = match height {
+        6 => (&ALPHABET_6, 4),
+        10 => (&ALPHABET_10, 6),
+        _ => return Err(OcrError::UnsupportedHeight(height)),
+    };
+    let min_y = min_y.unwrap();
+    let mut columns = dots.iter().map(|pos| pos.x()).collect::<Vec<_>>();
+    columns.sort_unstable();
+    columns.dedup();
+    // Split the non-empty columns into runs of consecutive columns
+    let mut glyphs = Vec::<(i32, i32)>::new();
+    for x in columns {
+        match glyphs.last_mut() {
+            Some((_, end)) if *end + 1 == x => *end = x,
+            _ => glyphs.push((x, x)),
+        }
+    }
+    glyphs
+        .into_iter()
+        .enumerate()
+        .map(|(index, (start, end))| {
+            let width = end - start + 1;
+            let render = |left: i32, glyph_width: i32| {
+                (min_y..min_y + height)
+                    .map(|y| {
+                        (left..left + glyph_width)
+                            .map(|x| match dots.contains(&Vector2D::new(x, y)) {
+                                true => '#',
+                                false => '.',
+                            })
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            alphabet
+                .iter()
+                .find(|&&(_, letter)| {
+                    let glyph_width = letter.find('\n').unwrap() as i32;
+                    (0..=glyph_width - width)
+                        .any(|offset| render(start - offset, glyph_width) == letter)
+                })
+                .map(|&(c, _)| c)
+                .ok_or_else(|| OcrError::UnknownGlyph(index, render(start, cell_width.max(width))))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(art: &str, offset: Vector2D) -> HashSet<Vector2D> {
+        art.lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '#')
+                    .map(move |(x, _)| Vector2D::new(x as i32, y as i32) + offset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recognize() {
+        let art = r"
+#..#.###..####.#....
+#..#..#...#....#....
+####..#...###..#....
+#..#..#...#....#....
+#..#..#...#....#....
+#..#.###..####.####."
+            .trim();
+        assert_eq!(
+            recognize(&draw(art, Vector2D::zero())),
+            Ok("HIEL".to_string())
+        );
+        // Starting with a letter that doesn't fill its cell
+        let art = art
+            .lines()
+            .map(|line| &line[5..])
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            recognize(&draw(&art, Vector2D::new(-3, 7))),
+            Ok("IEL".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recognize_large() {
+        let art = r"
+#....#..######
+##...#..#.....
+##...#..#.....
+#.#..#..#.....
+#.#..#..#####.
+#..#.#..#.....
+#..#.#..#.....
+#...##..#.....
+#...##..#.....
+#....#..######"
+            .trim();
+        assert_eq!(
+            recognize(&draw(art, Vector2D::zero())),
+            Ok("NE".to_string())
+        );
+    }
+
+    #[test]
+    fn test_alphabet() {
+        for alphabet in [&ALPHABET_6[..], &ALPHABET_10[..]] {
+            let mut dots = HashSet::new();
+            let mut x = 0;
+            for &(_, letter) in alphabet {
+                dots.extend(draw(letter, Vector2D::new(x, 0)));
+                x += letter.find('\n').unwrap() as i32 + 1;
+            }
+            let expected = alphabet.iter().map(|&(c, _)| c).collect::<String>();
+            assert_eq!(recognize(&dots), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn test_unrecognized() {
+        assert_eq!(
+            recognize(&HashSet::new()),
+            Err(OcrError::UnsupportedHeight(0))
+        );
+        let art = "##\n##\n##\n##\n##\n##";
+        assert_eq!(
+            recognize(&draw(art, Vector2D::zero())),
+            Err(OcrError::UnknownGlyph(
+                0,
+                "##..\n##..\n##..\n##..\n##..\n##..".to_string()
+            ))
+        );
+    }
+}